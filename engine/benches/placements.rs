@@ -0,0 +1,182 @@
+// Placement generation throughput: `cargo bench --bench placements`. The
+// `hashed_*` benches rebuild what this replaced, for comparison.
+use std::collections::{HashMap, HashSet};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use z_spin_engine::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use z_spin_engine::movegen::MoveMap;
+use z_spin_engine::pieces::{Piece, PieceType, Placement, PIECE_SHAPES};
+
+// Bottom rows of a few typical stacks, '#' filled
+const STACKS: [&[&str]; 4] = [
+    &[],
+    &[
+        "##.....###",
+        "###...####",
+        "####.#####",
+        "####.#####",
+    ],
+    &[
+        "#.........",
+        "##.....#..",
+        "###...###.",
+        "####.#####",
+        "##########",
+    ],
+    &[
+        ".....#....",
+        "...###....",
+        "#.#####..#",
+        "###.######",
+        "####.#####",
+        "#####.####",
+        "######.###",
+        "#######.##",
+    ],
+];
+
+fn boards() -> Vec<Board> {
+    STACKS
+        .iter()
+        .map(|rows| {
+            let mut board = Board::new();
+            for (i, row) in rows.iter().enumerate() {
+                let y = BOARD_HEIGHT - rows.len() + i;
+                for (x, cell) in row.chars().enumerate() {
+                    board.set_cell(x, y, cell == '#');
+                }
+            }
+            board
+        })
+        .collect()
+}
+
+// The old piece geometry: a mask for every (piece, rotation, x) inside the
+// walls, looked up in a HashMap on each collision check
+struct HashedMasks(HashMap<(PieceType, usize, i32), [u16; 4]>);
+
+impl HashedMasks {
+    fn new() -> Self {
+        let mut masks = HashMap::new();
+        for piece in PieceType::ALL {
+            for (rotation, shape) in PIECE_SHAPES[piece as usize].iter().enumerate() {
+                for x in -3..=BOARD_WIDTH as i32 {
+                    let shifted = shape.map(|row| if x < 0 { (row >> -x, row & ((1 << -x) - 1)) } else { (row << x, 0) });
+                    if shifted.iter().all(|&(row, lost)| lost == 0 && row < 1 << BOARD_WIDTH) {
+                        masks.insert((piece, rotation, x), shifted.map(|(row, _)| row));
+                    }
+                }
+            }
+        }
+        Self(masks)
+    }
+
+    fn can_place(&self, board: &Board, piece: &Piece) -> bool {
+        let Some(mask) = self.0.get(&(piece.piece_type, piece.rotation, piece.x)) else {
+            return false;
+        };
+        mask.iter().enumerate().all(|(i, &row)| {
+            let y = piece.y + i as i32;
+            row == 0 || (0..BOARD_HEIGHT as i32).contains(&y) && board.rows[y as usize] & row as u32 == 0
+        })
+    }
+
+    // The old generator: drop every rotation in every column from the top
+    fn placements(&self, board: &Board, piece_type: PieceType) -> Vec<Placement> {
+        let mut placements = Vec::new();
+        let mut seen = HashSet::new();
+        for rotation in 0..4 {
+            for x in -2..BOARD_WIDTH as i32 + 2 {
+                let mut piece = Piece::new(piece_type, x, 0).with_rotation(rotation);
+                if !self.can_place(board, &piece) {
+                    continue;
+                }
+                while self.can_place(board, &piece.moved(0, 1)) {
+                    piece.y += 1;
+                }
+                let placement = Placement::new(piece.x, piece.y, rotation);
+                if seen.insert(placement) {
+                    placements.push(placement);
+                }
+            }
+        }
+        placements
+    }
+}
+
+fn placement_generation(c: &mut Criterion) {
+    let boards = boards();
+    let hashed = HashedMasks::new();
+    let mut group = c.benchmark_group("placements");
+    group.throughput(Throughput::Elements((boards.len() * PieceType::ALL.len()) as u64));
+    group.bench_function("hashed_drop", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for board in &boards {
+                for piece in PieceType::ALL {
+                    count += hashed.placements(black_box(board), piece).len();
+                }
+            }
+            count
+        })
+    });
+    group.bench_function("move_map", |b| {
+        b.iter(|| {
+            let mut count = 0;
+            for board in &boards {
+                for piece in PieceType::ALL {
+                    count += MoveMap::new(black_box(board), piece).placements().len();
+                }
+            }
+            count
+        })
+    });
+    // What the search does with each placement: lock it and clear lines
+    group.bench_function("move_map_and_lock", |b| {
+        b.iter(|| {
+            let mut cleared = 0;
+            for board in &boards {
+                for piece in PieceType::ALL {
+                    for placement in MoveMap::new(black_box(board), piece).placements() {
+                        let mut next = board.clone();
+                        next.lock_piece(&Piece::new(piece, placement.x, placement.y).with_rotation(placement.rotation));
+                        cleared += next.clear_lines().lines_cleared;
+                    }
+                }
+            }
+            cleared
+        })
+    });
+    group.finish();
+}
+
+// Every piece, rotation and position on every board through `can_place_piece`
+fn collision_checks(c: &mut Criterion) {
+    let boards = boards();
+    let hashed = HashedMasks::new();
+    let positions = PieceType::ALL.len() * 4 * (BOARD_WIDTH + 4) * BOARD_HEIGHT;
+    let check_all = |can_place: &dyn Fn(&Board, &Piece) -> bool| {
+        let mut fits = 0;
+        for board in &boards {
+            for piece in PieceType::ALL {
+                for rotation in 0..4 {
+                    for x in -3..BOARD_WIDTH as i32 + 1 {
+                        for y in 0..BOARD_HEIGHT as i32 {
+                            let piece = Piece::new(piece, x, y).with_rotation(rotation);
+                            // Opaque piece, or the lookup is hoisted out of the y loop
+                            fits += can_place(black_box(board), black_box(&piece)) as usize;
+                        }
+                    }
+                }
+            }
+        }
+        fits
+    };
+    let mut group = c.benchmark_group("collision");
+    group.throughput(Throughput::Elements((boards.len() * positions) as u64));
+    group.bench_function("hashed", |b| b.iter(|| check_all(&|board, piece| hashed.can_place(board, piece))));
+    group.bench_function("can_place_piece", |b| b.iter(|| check_all(&|board, piece| board.can_place_piece(piece))));
+    group.finish();
+}
+
+criterion_group!(benches, placement_generation, collision_checks);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+// Finesse reference table: the fewest inputs for every piece, rotation and
+// column on an empty board, under ARR 0 and ARR above 0.
+//
+//   cargo run --example finesse_table            printable chart
+//   cargo run --example finesse_table -- --json  JSON
+//
+// The tests in `coach` replay every path to check the pathfinder.
+use z_spin_engine::coach::finesse_table;
+
+// Short names for the chart; the hard drop that ends every path is left out
+fn notation(action: &str) -> &'static str {
+    match action {
+        "rotate" => "CW",
+        "rotate_ccw" => "CCW",
+        "move_left" => "L",
+        "move_right" => "R",
+        "move_all_the_way_left" => "DL",
+        "move_all_the_way_right" => "DR",
+        "move_down" => "SD",
+        "move_to_bottom" => "SONIC",
+        _ => "",
+    }
+}
+
+fn chart_path(path: &[String]) -> String {
+    let moves: Vec<&str> = path.iter().map(|action| notation(action)).filter(|name| !name.is_empty()).collect();
+    if moves.is_empty() { "-".to_string() } else { moves.join(" ") }
+}
+
+fn main() {
+    let table = finesse_table();
+    if std::env::args().any(|arg| arg == "--json") {
+        println!("{}", serde_json::to_string_pretty(&table).expect("table serializes"));
+    } else {
+        let mut last = None;
+        for entry in &table {
+            if last != Some((entry.piece, entry.rotation)) {
+                println!("\n{:?} rotation {}", entry.piece, entry.rotation);
+                println!("  col  {:<24}ARR >0", "ARR 0");
+                last = Some((entry.piece, entry.rotation));
+            }
+            println!("  {:>3}  {:<24}{}", entry.column, chart_path(&entry.arr_zero), chart_path(&entry.arr_nonzero));
+        }
+    }
+}
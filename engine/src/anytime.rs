@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+use crate::bag::{BagRule, BagState};
+use crate::board::Board;
+use crate::evaluation::{EvaluationWeights, SpinKind};
+use crate::pieces::{PieceType, Placement};
+use crate::search::{parallel_map, PlacementEvaluation, SearchEngine, NO_PLACEMENT_SCORE};
+
+// Hard cap on tree size so a long think cannot exhaust memory
+const MAX_NODES: usize = 200_000;
+
+// Limits for one `think` call; with neither set it runs until the tree is done
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SearchBudget {
+    pub time_ms: Option<f64>,
+    pub nodes: Option<usize>,
+}
+
+impl SearchBudget {
+    pub fn time(time_ms: f64) -> Self {
+        Self { time_ms: Some(time_ms), nodes: None }
+    }
+
+    pub fn nodes(nodes: usize) -> Self {
+        Self { time_ms: None, nodes: Some(nodes) }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnytimeBest {
+    pub placement: Placement,
+    pub eval: PlacementEvaluation,
+    pub value: f64,
+    // Pieces of lookahead behind `value`, counting the current one
+    pub level: usize,
+}
+
+// Placements of one piece from a node. Known pieces have weight 1; past the
+// preview every piece the bag can deal is a group of equal weight.
+struct Group {
+    piece: PieceType,
+    weight: f64,
+    children: Vec<(Placement, usize)>,
+}
+
+struct TreeNode {
+    board: Board,
+    // Score of the placement that led here, its reward included
+    static_score: f64,
+    reward: f64,
+    lines_cleared: usize,
+    spin: SpinKind,
+    value: f64,
+    // Placements between the root and this node
+    depth: usize,
+    // What the bag owes after the known queue and any chance pieces above
+    bag: BagState,
+    groups: Option<Vec<Group>>,
+}
+
+// Expectimax tree grown breadth-first (iterative deepening), one node
+// expansion per step, so `think` can stop almost anywhere. The best move of
+// the last finished layer is always available.
+//
+// The tree outlives a move: `commit` re-roots it at the placement that was
+// played and `reveal` folds newly previewed pieces into it, so the work done
+// while the last piece was falling carries over.
+pub struct AnytimeSearch {
+    // Known pieces from the root, the one to place first
+    queue: Vec<PieceType>,
+    weights: EvaluationWeights,
+    // Chance layers to search past the preview, as the engine is configured
+    chance_depth: usize,
+    bag_rule: BagRule,
+    // What the bag owes after `queue`
+    bag: BagState,
+    nodes: Vec<TreeNode>,
+    // Nodes still to expand, shallowest first
+    frontier: VecDeque<usize>,
+    level: usize,
+    best: Option<AnytimeBest>,
+    finished: bool,
+}
+
+impl AnytimeSearch {
+    pub fn new(engine: &SearchEngine, board: &Board, piece: PieceType, queue: &[PieceType], weights: EvaluationWeights) -> Self {
+        let mut search = Self::with_root(engine, board, piece, queue, weights);
+        search.expand(engine, 0);
+        search.finish_level();
+        search
+    }
+
+    // A search whose answer is already settled, like the next step of a
+    // perfect clear; there is nothing to deepen.
+    pub fn solved(engine: &SearchEngine, board: &Board, piece: PieceType, queue: &[PieceType], weights: EvaluationWeights, placement: Placement) -> Self {
+        let mut search = Self::with_root(engine, board, piece, queue, weights);
+        search.best = engine.evaluate_placement(board, piece, &placement, &search.weights)
+            .map(|eval| AnytimeBest { placement, eval, value: f64::INFINITY, level: 1 });
+        search.finished = true;
+        search
+    }
+
+    fn with_root(engine: &SearchEngine, board: &Board, piece: PieceType, queue: &[PieceType], weights: EvaluationWeights) -> Self {
+        let mut known = Vec::with_capacity(queue.len() + 1);
+        known.push(piece);
+        known.extend_from_slice(queue);
+
+        let root = TreeNode {
+            board: board.without_colors(),
+            static_score: board.evaluate(&weights).score,
+            reward: 0.0,
+            lines_cleared: 0,
+            spin: SpinKind::None,
+            value: 0.0,
+            depth: 0,
+            bag: engine.bag(),
+            groups: None,
+        };
+
+        Self {
+            queue: known,
+            weights,
+            chance_depth: engine.chance_depth(),
+            bag_rule: engine.bag_rule(),
+            bag: engine.bag(),
+            nodes: vec![root],
+            frontier: VecDeque::new(),
+            level: 0,
+            best: None,
+            finished: false,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.nodes[0].board
+    }
+
+    // None after committing past the end of the preview, until `reveal`
+    pub fn piece(&self) -> Option<PieceType> {
+        self.queue.first().copied()
+    }
+
+    pub fn weights(&self) -> &EvaluationWeights {
+        &self.weights
+    }
+
+    pub fn best(&self) -> Option<&AnytimeBest> {
+        self.best.as_ref()
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Search until the budget runs out or the tree is complete. Returns true
+    // once there is nothing left to deepen.
+    pub fn think(&mut self, engine: &SearchEngine, budget: SearchBudget) -> bool {
+        let start_ms = now_ms();
+        let start_nodes = engine.nodes_searched();
+
+        while !self.finished {
+            if budget.time_ms.is_some_and(|limit| now_ms() - start_ms >= limit)
+                || budget.nodes.is_some_and(|limit| engine.nodes_searched() - start_nodes >= limit)
+            {
+                break;
+            }
+            self.step(engine);
+        }
+        self.finished
+    }
+
+    // Search until every line `level` pieces deep has been looked at
+    pub fn think_until(&mut self, engine: &SearchEngine, level: usize) -> bool {
+        while !self.finished && self.level < level {
+            self.step(engine);
+        }
+        self.finished
+    }
+
+    // Make the node reached by placing the root piece at `placement` the new
+    // root, dropping its siblings. Returns false if the tree never got there.
+    pub fn commit(&mut self, placement: &Placement) -> bool {
+        let child = self.root_children()
+            .and_then(|children| children.iter().find(|(candidate, _)| candidate == placement))
+            .map(|&(_, child)| child);
+        let Some(child) = child else {
+            return false;
+        };
+
+        self.queue.remove(0);
+        self.rebuild(child);
+        true
+    }
+
+    // Commit whichever root placement leads to `board`
+    pub fn commit_board(&mut self, board: &Board) -> bool {
+        let placement = self.root_children()
+            .and_then(|children| children.iter().find(|&&(_, child)| self.nodes[child].board == *board))
+            .map(|&(placement, _)| placement);
+        placement.is_some_and(|placement| self.commit(&placement))
+    }
+
+    // `queue` is everything known now, root piece first, and `bag` what the
+    // bag owes after it. Each newly seen piece collapses its chance layer to
+    // the one outcome that happened. Returns false, leaving the tree as it
+    // was, if that contradicts what the tree assumed.
+    pub fn reveal(&mut self, queue: &[PieceType], bag: BagState) -> bool {
+        if !queue.starts_with(&self.queue) {
+            return false;
+        }
+
+        let mut expected = self.bag;
+        for &piece in &queue[self.queue.len()..] {
+            if !self.bag_rule.candidates(&expected).contains(&piece) {
+                return false;
+            }
+            expected = expected.take(piece);
+        }
+        if expected != bag {
+            return false;
+        }
+
+        for (depth, &piece) in queue.iter().enumerate().skip(self.queue.len()) {
+            for node in self.nodes.iter_mut().filter(|node| node.depth == depth) {
+                let Some(groups) = node.groups.as_mut() else { continue };
+                groups.retain(|group| group.piece == piece);
+                match groups.first_mut() {
+                    Some(group) => group.weight = 1.0,
+                    None => node.groups = None,
+                }
+            }
+        }
+
+        self.queue = queue.to_vec();
+        self.bag = bag;
+        self.rebuild(0);
+        true
+    }
+
+    // Placements of the root piece, once it is known and expanded
+    fn root_children(&self) -> Option<&[(Placement, usize)]> {
+        self.queue.first()?;
+        self.nodes[0].groups.as_ref()?.first().map(|group| group.children.as_slice())
+    }
+
+    fn expandable(&self, depth: usize) -> bool {
+        depth < self.queue.len() + self.chance_depth
+    }
+
+    fn step(&mut self, engine: &SearchEngine) {
+        let Some(index) = self.frontier.pop_front() else {
+            self.finish_level();
+            return;
+        };
+        self.expand(engine, index);
+
+        if self.frontier.front().is_none_or(|&next| self.nodes[next].depth > self.level) {
+            self.finish_level();
+        }
+    }
+
+    fn expand(&mut self, engine: &SearchEngine, index: usize) {
+        let node = &self.nodes[index];
+        let (board, depth, bag) = (node.board.clone(), node.depth, node.bag);
+
+        // (piece, weight, bag after it)
+        let outcomes: Vec<(PieceType, f64, BagState)> = match self.queue.get(depth) {
+            Some(&piece) => vec![(piece, 1.0, bag)],
+            None => {
+                let pieces = self.bag_rule.candidates(&bag);
+                let weight = 1.0 / pieces.len() as f64;
+                pieces.into_iter().map(|piece| (piece, weight, bag.take(piece))).collect()
+            }
+        };
+
+        // Chance outcomes are ranked in parallel; nodes are added in outcome order
+        let weights = &self.weights;
+        let ranked = parallel_map(engine.is_parallel(), &outcomes, |&(piece, ..)| {
+            let mut ranked = engine.rank_placements(&board, piece, weights);
+            ranked.truncate(engine.beam_width());
+            ranked
+        });
+
+        // Best-ranked children go first so they are expanded first
+        let children_expandable = self.expandable(depth + 1);
+        let mut groups = Vec::with_capacity(outcomes.len());
+        for ((piece, weight, child_bag), beam) in outcomes.into_iter().zip(ranked) {
+            let mut children = Vec::new();
+            for (placement, eval) in beam {
+                self.nodes.push(TreeNode {
+                    board: eval.predicted_board,
+                    static_score: eval.score,
+                    reward: eval.reward,
+                    lines_cleared: eval.lines_cleared,
+                    spin: eval.spin,
+                    value: eval.score,
+                    depth: depth + 1,
+                    bag: child_bag,
+                    groups: None,
+                });
+                let child = self.nodes.len() - 1;
+                if children_expandable {
+                    self.frontier.push_back(child);
+                }
+                children.push((placement, child));
+            }
+            groups.push(Group { piece, weight, children });
+        }
+        self.nodes[index].groups = Some(groups);
+    }
+
+    // Everything above the next frontier node is expanded: back values up to
+    // the root and record the best move.
+    fn finish_level(&mut self) {
+        self.backup(0);
+        self.level = match self.frontier.front() {
+            Some(&next) => self.nodes[next].depth,
+            None => self.queue.len() + self.chance_depth,
+        };
+
+        self.best = self.root_children().and_then(|children| {
+            children
+                .iter()
+                .map(|&(placement, child)| (placement, &self.nodes[child]))
+                .reduce(|best, candidate| if candidate.1.value > best.1.value { candidate } else { best })
+                .map(|(placement, node)| AnytimeBest {
+                    placement,
+                    eval: PlacementEvaluation {
+                        score: node.static_score,
+                        reward: node.reward,
+                        predicted_board: node.board.clone(),
+                        lines_cleared: node.lines_cleared,
+                        spin: node.spin,
+                    },
+                    value: node.value,
+                    level: self.level,
+                })
+        });
+
+        self.finished = self.frontier.is_empty() || self.nodes.len() >= MAX_NODES;
+    }
+
+    // A node's value is its own reward plus what its children are worth, so
+    // clears and spins above the deepest layer still count.
+    fn backup(&mut self, index: usize) -> f64 {
+        let Some(groups) = self.nodes[index].groups.take() else {
+            return self.nodes[index].static_score;
+        };
+
+        let mut value = 0.0;
+        for group in &groups {
+            let best = group.children
+                .iter()
+                .map(|&(_, child)| self.backup(child))
+                .fold(NO_PLACEMENT_SCORE, f64::max);
+            value += group.weight * best;
+        }
+
+        let node = &mut self.nodes[index];
+        node.groups = Some(groups);
+        node.value = node.reward + value;
+        node.value
+    }
+
+    // Copy the subtree under `root` into a fresh arena in breadth-first order,
+    // freeing everything else, then queue whatever is now worth expanding.
+    fn rebuild(&mut self, root: usize) {
+        let base_depth = self.nodes[root].depth;
+        let mut old: Vec<Option<TreeNode>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        let mut pending = VecDeque::from([root]);
+
+        while let Some(index) = pending.pop_front() {
+            let Some(mut node) = old[index].take() else { continue };
+            node.depth -= base_depth;
+            for group in node.groups.iter_mut().flatten() {
+                for (_, child) in &mut group.children {
+                    pending.push_back(*child);
+                    // Slots are handed out in the order nodes leave `pending`
+                    *child = self.nodes.len() + pending.len();
+                }
+            }
+            self.nodes.push(node);
+        }
+
+        self.frontier = (0..self.nodes.len())
+            .filter(|&index| self.nodes[index].groups.is_none() && self.expandable(self.nodes[index].depth))
+            .collect();
+        self.finished = false;
+        self.finish_level();
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+// No clock without JavaScript: time budgets never run out, node budgets still do
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+pub fn now_ms() -> f64 {
+    0.0
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    use once_cell::sync::Lazy;
+    use std::time::Instant;
+
+    static START: Lazy<Instant> = Lazy::new(Instant::now);
+    START.elapsed().as_secs_f64() * 1000.0
+}
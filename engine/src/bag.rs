@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+use crate::pieces::PieceType;
+
+// How the randomizer deals pieces
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BagRule {
+    SevenBag,
+    Random,
+}
+
+// Pieces still to be dealt from the current 7-bag, one bit per `PieceType`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BagState {
+    pub remaining: u8,
+}
+
+const FULL_BAG: u8 = 0b111_1111;
+
+impl BagState {
+    pub fn full() -> Self {
+        Self { remaining: FULL_BAG }
+    }
+
+    // An empty list means the next piece starts a fresh bag
+    pub fn from_remaining(pieces: &[PieceType]) -> Self {
+        let remaining = pieces.iter().fold(0, |bits, &piece| bits | piece_bit(piece));
+        if remaining == 0 { Self::full() } else { Self { remaining } }
+    }
+
+    pub fn contains(&self, piece: PieceType) -> bool {
+        self.remaining & piece_bit(piece) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.remaining.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn pieces(&self) -> impl Iterator<Item = PieceType> + '_ {
+        PieceType::ALL.into_iter().filter(move |&piece| self.contains(piece))
+    }
+
+    // Work out where the 7-bag cycle is from the pieces dealt so far. Phases that
+    // fit the sequence are merged; if none fits (e.g. a new game started), the
+    // oldest pieces are dropped until one does.
+    pub fn infer(observed: &[PieceType]) -> Self {
+        for start in 0..observed.len() {
+            let remaining = (0..7)
+                .filter_map(|phase| remaining_for_phase(&observed[start..], phase))
+                .fold(0, |bits, remaining| bits | remaining);
+            if remaining != 0 {
+                return Self { remaining };
+            }
+        }
+        Self::full()
+    }
+
+    // Deal `piece`, starting a new bag once the current one runs out
+    pub fn take(&self, piece: PieceType) -> Self {
+        let remaining = self.remaining & !piece_bit(piece);
+        if remaining == 0 { Self::full() } else { Self { remaining } }
+    }
+}
+
+impl Default for BagState {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+// SplitMix64: tiny, seedable and identical on every target, so seeded games
+// and searches replay exactly
+#[derive(Clone, Debug)]
+pub struct PieceRng {
+    state: u64,
+}
+
+impl PieceRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (((self.next_u64() >> 32) * n as u64) >> 32) as usize
+    }
+}
+
+impl BagRule {
+    // Draw the next piece the randomizer could deal from `bag`
+    pub fn deal(&self, bag: &BagState, rng: &mut PieceRng) -> PieceType {
+        let candidates = self.candidates(bag);
+        candidates[rng.below(candidates.len())]
+    }
+
+    // Pieces that can be dealt next from `bag`
+    pub fn candidates(&self, bag: &BagState) -> Vec<PieceType> {
+        match self {
+            BagRule::SevenBag => bag.pieces().collect(),
+            BagRule::Random => PieceType::ALL.to_vec(),
+        }
+    }
+
+    // Every order in which the next `length` pieces can be dealt
+    pub fn sequences(&self, bag: BagState, length: usize) -> Vec<Vec<PieceType>> {
+        let mut sequences = Vec::new();
+        let mut current = Vec::with_capacity(length);
+        self.extend_sequences(bag, length, &mut current, &mut sequences);
+        sequences
+    }
+
+    fn extend_sequences(&self, bag: BagState, length: usize, current: &mut Vec<PieceType>, sequences: &mut Vec<Vec<PieceType>>) {
+        if current.len() == length {
+            sequences.push(current.clone());
+            return;
+        }
+        for piece in self.candidates(&bag) {
+            current.push(piece);
+            self.extend_sequences(bag.take(piece), length, current, sequences);
+            current.pop();
+        }
+    }
+}
+
+// `phase` is the size of the first, partial bag in `sequence`. Returns what the
+// last bag still owes, or None if some bag would deal a piece twice.
+fn remaining_for_phase(sequence: &[PieceType], phase: usize) -> Option<u8> {
+    let mut dealt = 0u8;
+    for (i, &piece) in sequence.iter().enumerate() {
+        if i >= phase && (i - phase).is_multiple_of(7) {
+            dealt = 0;
+        }
+        if dealt & piece_bit(piece) != 0 {
+            return None;
+        }
+        dealt |= piece_bit(piece);
+    }
+    if sequence.len() == phase {
+        return Some(FULL_BAG); // The partial first bag just ran out
+    }
+    let remaining = FULL_BAG & !dealt;
+    Some(if remaining == 0 { FULL_BAG } else { remaining })
+}
+
+fn piece_bit(piece: PieceType) -> u8 {
+    1 << piece as u8
+}
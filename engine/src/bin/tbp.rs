@@ -0,0 +1,280 @@
+// Tetris Bot Protocol (TBP) frontend: one JSON message per line on stdin,
+// replies on stdout. The bot never holds; when the frontend plays a held
+// piece anyway, the queue and hold slot follow along.
+//
+//   cargo run --release --bin tbp -- [--strategy balanced|aggressive|defensive|tspan]
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use serde::{Deserialize, Serialize};
+use z_spin_engine::bag::{BagRule, BagState};
+use z_spin_engine::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use z_spin_engine::evaluation::{EvaluationWeights, SpinKind, Strategy};
+use z_spin_engine::pieces::{Piece, PieceType, Placement};
+use z_spin_engine::search::{SearchEngine, SearchState};
+
+// Moves offered per suggestion, best first
+const SUGGESTIONS: usize = 4;
+// Enough history to pin down the bag phase; trimmed a whole bag at a time
+const PIECE_HISTORY_LIMIT: usize = 28;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules {},
+    Start(Start),
+    Stop {},
+    Suggest {},
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece { piece: PieceType },
+    Quit {},
+    // The protocol asks bots to ignore messages they do not know
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize)]
+struct Start {
+    hold: Option<PieceType>,
+    queue: Vec<PieceType>,
+    #[serde(default)]
+    combo: u32,
+    #[serde(default)]
+    back_to_back: bool,
+    // 40 rows of 10 cells, bottom row first; any non-null cell is filled
+    board: Vec<Vec<Option<String>>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info { name: &'static str, version: &'static str, author: &'static str, features: Vec<String> },
+    Ready,
+    Suggestion { moves: Vec<Move> },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Move {
+    location: Location,
+    #[serde(default)]
+    spin: Spin,
+}
+
+// SRS centre of the piece, with y counting up from the bottom row
+#[derive(Serialize, Deserialize)]
+struct Location {
+    #[serde(rename = "type")]
+    piece: PieceType,
+    orientation: Orientation,
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Spin {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+impl From<SpinKind> for Spin {
+    fn from(spin: SpinKind) -> Self {
+        match spin {
+            SpinKind::None => Spin::None,
+            SpinKind::Mini => Spin::Mini,
+            SpinKind::Full => Spin::Full,
+        }
+    }
+}
+
+impl Orientation {
+    const ALL: [Orientation; 4] = [Orientation::North, Orientation::East, Orientation::South, Orientation::West];
+
+    fn rotation(self) -> usize {
+        self as usize
+    }
+}
+
+// TBP rows count up from the bottom; engine rows count down from the top
+fn flip_row(y: i32) -> i32 {
+    BOARD_HEIGHT as i32 - 1 - y
+}
+
+impl Location {
+    fn from_placement(piece: PieceType, placement: &Placement) -> Self {
+        let (cx, cy) = Piece::srs_centre(piece, placement.rotation);
+        Self {
+            piece,
+            orientation: Orientation::ALL[placement.rotation % 4],
+            x: placement.x + cx,
+            y: flip_row(placement.y + cy),
+        }
+    }
+
+    fn to_piece(&self) -> Piece {
+        let rotation = self.orientation.rotation();
+        let (cx, cy) = Piece::srs_centre(self.piece, rotation);
+        Piece::new(self.piece, self.x - cx, flip_row(self.y) - cy).with_rotation(rotation)
+    }
+}
+
+struct Bot {
+    search: SearchEngine,
+    strategy: Strategy,
+    board: Board,
+    // Current piece first, then the preview
+    queue: VecDeque<PieceType>,
+    hold: Option<PieceType>,
+    // Clears in a row and whether the last clear was a tetris or a spin
+    combo: u32,
+    b2b: bool,
+    seen: Vec<PieceType>,
+}
+
+impl Bot {
+    fn new(strategy: Strategy) -> Self {
+        Self {
+            search: SearchEngine::new(),
+            strategy,
+            board: Board::new(),
+            queue: VecDeque::new(),
+            hold: None,
+            combo: 0,
+            b2b: false,
+            seen: Vec::new(),
+        }
+    }
+
+    fn handle(&mut self, message: FrontendMessage) -> Option<BotMessage> {
+        match message {
+            FrontendMessage::Rules {} => return Some(BotMessage::Ready),
+            FrontendMessage::Start(start) => self.start(start),
+            FrontendMessage::Stop {} => {
+                self.queue.clear();
+                self.hold = None;
+            }
+            FrontendMessage::Suggest {} => return Some(BotMessage::Suggestion { moves: self.suggest() }),
+            FrontendMessage::Play { mv } => self.play(&mv),
+            FrontendMessage::NewPiece { piece } => {
+                self.queue.push_back(piece);
+                self.observe(piece);
+            }
+            FrontendMessage::Quit {} | FrontendMessage::Unknown => {}
+        }
+        None
+    }
+
+    fn start(&mut self, start: Start) {
+        self.board = Board::new();
+        for (row, cells) in start.board.iter().take(BOARD_HEIGHT).enumerate() {
+            for (x, cell) in cells.iter().take(BOARD_WIDTH).enumerate() {
+                self.board.set_cell(x, flip_row(row as i32) as usize, cell.is_some());
+            }
+        }
+        self.hold = start.hold;
+        self.combo = start.combo;
+        self.b2b = start.back_to_back;
+        self.queue = start.queue.iter().copied().collect();
+        self.seen.clear();
+        for piece in start.queue {
+            self.observe(piece);
+        }
+    }
+
+    fn observe(&mut self, piece: PieceType) {
+        self.seen.push(piece);
+        if self.seen.len() > PIECE_HISTORY_LIMIT {
+            self.seen.drain(..7);
+        }
+    }
+
+    fn suggest(&mut self) -> Vec<Move> {
+        let Some(&current) = self.queue.front() else {
+            return Vec::new();
+        };
+        let preview: Vec<PieceType> = self.queue.iter().skip(1).copied().collect();
+        self.search.set_bag(BagRule::SevenBag, BagState::infer(&self.seen));
+
+        let weights = EvaluationWeights::new(self.strategy);
+        let root = SearchState::new(self.board.clone()).with_streak(self.combo, self.b2b);
+        self.search
+            .top_placements_from(&root, current, &preview, &weights, SUGGESTIONS, 0, 0)
+            .into_iter()
+            .map(|ranked| Move { location: Location::from_placement(current, &ranked.placement), spin: ranked.spin.into() })
+            .collect()
+    }
+
+    fn play(&mut self, mv: &Move) {
+        let played = mv.location.piece;
+        if self.queue.front() == Some(&played) {
+            self.queue.pop_front();
+        } else if self.hold == Some(played) {
+            self.hold = self.queue.pop_front();
+        } else if self.hold.is_none() && self.queue.get(1) == Some(&played) {
+            // Hold was empty: the current piece went in and the next one played
+            self.hold = self.queue.pop_front();
+            self.queue.pop_front();
+        } else {
+            eprintln!("tbp: {:?} was played but is neither current, held nor next; waiting for a new start", played);
+            self.queue.clear();
+            self.hold = None;
+            return;
+        }
+
+        self.board.lock_piece(&mv.location.to_piece());
+        let lines = self.board.clear_lines().lines_cleared;
+        if lines == 0 {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.b2b = lines >= 4 || mv.spin != Spin::None;
+        }
+    }
+}
+
+fn send(message: &BotMessage) {
+    println!("{}", serde_json::to_string(message).expect("bot messages serialize"));
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let strategy = match args.iter().position(|arg| arg == "--strategy") {
+        Some(index) => match args.get(index + 1).and_then(|name| Strategy::from_name(name)) {
+            Some(strategy) => strategy,
+            None => {
+                eprintln!("usage: tbp [--strategy balanced|aggressive|defensive|tspan]");
+                std::process::exit(2);
+            }
+        },
+        None => Strategy::Balanced,
+    };
+
+    let mut bot = Bot::new(strategy);
+    send(&BotMessage::Info { name: "z-spin", version: env!("CARGO_PKG_VERSION"), author: "z-spin", features: Vec::new() });
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        match serde_json::from_str::<FrontendMessage>(&line) {
+            Ok(FrontendMessage::Quit {}) => break,
+            Ok(message) => {
+                if let Some(reply) = bot.handle(message) {
+                    send(&reply);
+                }
+            }
+            Err(error) => eprintln!("tbp: ignoring message: {}", error),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+// Command-line driver for headless games and offline analysis
+//
+//   z-spin play [--games N] [--seed S] [--pieces N] [--strategy NAME] [--preview N] [--record DIR] [--format json|binary]
+//   z-spin analyze BOARD [QUEUE] [--strategy NAME] [--count N]
+//   z-spin replay REPLAY_FILE [--fumen FILE]
+//   z-spin verify REPLAY_FILE --score N --lines N --time MS
+//
+// Recorded games are replays as `replay::Replay` writes them, in either
+// encoding.
+//
+// A board is a file holding a text grid as `parse::parse_board` reads it (10
+// cells per row, bottom row last, '.' empty and '#' filled) or a v115 fumen,
+// whose first page is analyzed. A queue is piece letters, current first; a
+// fumen with a `#Q=` queue comment needs none.
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
+use z_spin_engine::board::Board;
+use z_spin_engine::evaluation::{EvaluationWeights, Strategy};
+use z_spin_engine::fumen::{self, FumenPage};
+use z_spin_engine::pc::PerfectClearSolver;
+use z_spin_engine::pieces::{Piece, PieceType};
+use z_spin_engine::render::{Charset, RenderOptions};
+use z_spin_engine::search::SearchEngine;
+use z_spin_engine::replay::{Recorder, Replay, ReplayPlayer};
+use z_spin_engine::sim::{GameStats, Handling, Ruleset};
+use z_spin_engine::verify::{self, ClaimedResult};
+
+const USAGE: &str = "usage:
+  z-spin play [--games N] [--seed S] [--pieces N] [--strategy NAME] [--preview N] [--record DIR] [--format json|binary]
+  z-spin analyze BOARD [QUEUE] [--strategy NAME] [--count N]
+  z-spin replay REPLAY_FILE [--fumen FILE]
+  z-spin verify REPLAY_FILE --score N --lines N --time MS
+strategies: balanced, aggressive, defensive, tspan";
+
+// Positional arguments plus `--name value` options
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self { positional: Vec::new(), options: Vec::new() };
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args.next().ok_or_else(|| format!("--{} needs a value", name))?;
+                    parsed.options.push((name.to_string(), value));
+                }
+                None => parsed.positional.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(option, _)| option == name).map(|(_, value)| value.as_str())
+    }
+
+    fn option<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.value(name) {
+            Some(value) => value.parse().map_err(|_| format!("bad value for --{}: {}", name, value)),
+            None => Ok(default),
+        }
+    }
+
+    fn strategy(&self) -> Result<Strategy, String> {
+        let name = self.value("strategy").unwrap_or(Strategy::Balanced.name());
+        Strategy::from_name(name).ok_or_else(|| format!("unknown strategy: {}", name))
+    }
+}
+
+fn parse_piece(letter: char) -> Result<PieceType, String> {
+    match letter.to_ascii_uppercase() {
+        'I' => Ok(PieceType::I),
+        'O' => Ok(PieceType::O),
+        'T' => Ok(PieceType::T),
+        'S' => Ok(PieceType::S),
+        'Z' => Ok(PieceType::Z),
+        'J' => Ok(PieceType::J),
+        'L' => Ok(PieceType::L),
+        _ => Err(format!("unknown piece: {}", letter)),
+    }
+}
+
+fn print_stats(label: &str, stats: &GameStats) {
+    println!(
+        "{:<12} pieces {:>5}  lines {:>5}  score {:>7}  tetrises {:>3}  spins {:>3} (+{} mini)  perfect clears {:>2}",
+        label, stats.pieces, stats.lines, stats.score, stats.clears[4], stats.spins, stats.mini_spins, stats.perfect_clears
+    );
+}
+
+fn play(args: &Args) -> Result<(), String> {
+    let games: u64 = args.option("games", 1)?;
+    let seed: u64 = args.option("seed", 1)?;
+    let max_pieces: usize = args.option("pieces", 500)?;
+    let rules = Ruleset { preview: args.option("preview", Ruleset::default().preview)?, ..Ruleset::default() };
+    let strategy = args.strategy()?;
+    let record = args.value("record");
+    let binary = match args.value("format").unwrap_or("json") {
+        "json" => false,
+        "binary" => true,
+        format => return Err(format!("unknown format: {}", format)),
+    };
+
+    let mut total = GameStats::default();
+    let mut topped_out = 0;
+    let started = Instant::now();
+    for game_seed in seed..seed + games {
+        let mut search = SearchEngine::new();
+        search.set_perfect_clear(Some(PerfectClearSolver::default()));
+        let mut recorder = Recorder::new(game_seed, rules, Handling::default());
+        recorder.play_bot(&mut search, strategy, max_pieces);
+
+        let game = recorder.game();
+        let stats = game.stats();
+        print_stats(&format!("seed {}", game_seed), stats);
+        topped_out += game.is_over() as usize;
+        total.pieces += stats.pieces;
+        total.lines += stats.lines;
+        total.score += stats.score;
+        for (sum, count) in total.clears.iter_mut().zip(stats.clears) {
+            *sum += count;
+        }
+        total.spins += stats.spins;
+        total.mini_spins += stats.mini_spins;
+        total.perfect_clears += stats.perfect_clears;
+
+        if let Some(dir) = record {
+            let replay = recorder.replay();
+            let (name, bytes) = if binary {
+                (format!("game-{}.zsr", game_seed), replay.to_bytes())
+            } else {
+                (format!("game-{}.json", game_seed), replay.to_json().into_bytes())
+            };
+            let path = Path::new(dir).join(name);
+            std::fs::write(&path, bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    if games > 1 {
+        print_stats("total", &total);
+    }
+    println!(
+        "{} games, {} topped out, {:.2} lines per piece, {:.1} ms per piece",
+        games,
+        topped_out,
+        total.lines as f64 / total.pieces.max(1) as f64,
+        elapsed * 1000.0 / total.pieces.max(1) as f64
+    );
+    Ok(())
+}
+
+// The board a fumen or a board file holds, and the queue a fumen comment gives
+fn read_board(source: &str) -> Result<(Board, Option<Vec<PieceType>>), String> {
+    if source.contains("115@") {
+        let page = fumen::decode(source)
+            .map_err(|error| error.to_string())?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "the fumen has no pages".to_string())?;
+        let queue = page.queue().map(|queue| queue.current.into_iter().chain(queue.next).collect());
+        return Ok((page.board, queue));
+    }
+    let text = std::fs::read_to_string(source).map_err(|error| format!("{}: {}", source, error))?;
+    let board = text.parse().map_err(|error| format!("{}: {}", source, error))?;
+    Ok((board, None))
+}
+
+fn analyze(args: &Args) -> Result<(), String> {
+    let (board, queue) = match &args.positional[..] {
+        [source] => match read_board(source)? {
+            (board, Some(queue)) => (board, queue),
+            (_, None) => return Err("analyze needs a queue unless the fumen has one".to_string()),
+        },
+        [source, queue] => (read_board(source)?.0, queue.chars().map(parse_piece).collect::<Result<Vec<_>, _>>()?),
+        _ => return Err("analyze takes a board and a queue".to_string()),
+    };
+    let Some((&current, preview)) = queue.split_first() else {
+        return Err("the queue is empty".to_string());
+    };
+    let count: usize = args.option("count", 5)?;
+    let weights = EvaluationWeights::new(args.strategy()?);
+
+    let ranked = SearchEngine::new().top_placements(&board, current, preview, &weights, count, 0, 0);
+    let Some(best) = ranked.first() else {
+        println!("{:?} has nowhere to go", current);
+        return Ok(());
+    };
+    let piece = Piece::new(current, best.placement.x, best.placement.y).with_rotation(best.placement.rotation);
+    println!("{}\n", board.render(&RenderOptions::default().with_charset(Charset::Unicode).with_piece(piece, false)));
+    for (rank, candidate) in ranked.iter().enumerate() {
+        let cells: Vec<String> = candidate.cells.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+        println!(
+            "{:>2}. x {:>2} y {:>2} rot {}  value {:>8.2}  score {:>8.2}  lines {}  spin {:?}  cells {}",
+            rank + 1,
+            candidate.placement.x,
+            candidate.placement.y,
+            candidate.placement.rotation,
+            candidate.value,
+            candidate.score,
+            candidate.lines_cleared,
+            candidate.spin,
+            cells.join(" ")
+        );
+        println!("    {}", candidate.moves.join(","));
+    }
+    let pages: Vec<FumenPage> = ranked
+        .iter()
+        .enumerate()
+        .map(|(rank, candidate)| {
+            FumenPage::new(board.clone()).with_piece(current, candidate.placement).with_comment(&format!("{}. value {:.2}", rank + 1, candidate.value)).with_lock(false)
+        })
+        .collect();
+    println!("\n{}", fumen::encode(&pages));
+    Ok(())
+}
+
+fn read_replay(args: &Args, command: &str) -> Result<Replay, String> {
+    let [replay_file] = &args.positional[..] else {
+        return Err(format!("{} takes a replay file", command));
+    };
+    let bytes = std::fs::read(replay_file).map_err(|error| format!("{}: {}", replay_file, error))?;
+    Replay::load(&bytes).map_err(|error| format!("{}: {}", replay_file, error))
+}
+
+fn replay(args: &Args) -> Result<(), String> {
+    let replay = read_replay(args, "replay")?;
+
+    match ReplayPlayer::new(&replay).run() {
+        Ok(game) => {
+            let placements = game.placements();
+            print_stats(&format!("seed {}", replay.seed), game.stats());
+            println!("{} pieces over {} frames replayed{}", placements.len(), replay.frames(), if game.is_over() { ", topped out" } else { "" });
+            if let Some(path) = args.value("fumen") {
+                let pages = fumen::placement_pages(&Board::new(), placements);
+                std::fs::write(path, fumen::encode(&pages)).map_err(|error| format!("{}: {}", path, error))?;
+            }
+            Ok(())
+        }
+        Err(divergence) => Err(format!(
+            "frame {}: event {} ({:?}): {}",
+            divergence.frame,
+            divergence.event + 1,
+            replay.events[divergence.event],
+            divergence.fault
+        )),
+    }
+}
+
+fn verify(args: &Args) -> Result<(), String> {
+    let replay = read_replay(args, "verify")?;
+    if let Some(name) = ["score", "lines", "time"].into_iter().find(|name| args.value(name).is_none()) {
+        return Err(format!("verify needs --{}", name));
+    }
+    let claimed = ClaimedResult { score: args.option("score", 0)?, lines: args.option("lines", 0)?, time_ms: args.option("time", 0)? };
+
+    let actual = verify::verify(&replay, &claimed).map_err(|rejection| format!("rejected at {}", rejection))?;
+    println!("accepted: {} points, {} lines in {} ms", actual.score, actual.lines, actual.time_ms);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let result = Args::parse(args).and_then(|args| match command.as_deref() {
+        Some("play") => play(&args),
+        Some("analyze") => analyze(&args),
+        Some("replay") => replay(&args),
+        Some("verify") => verify(&args),
+        _ => Err(USAGE.to_string()),
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("z-spin: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::pieces::Piece;
+use crate::pieces::{Piece, PieceType};
+use crate::render::{Charset, RenderOptions};
+use crate::zobrist::{self, CELL_KEYS};
 
 // Game board dimensions
 pub const BOARD_WIDTH: usize = 10;
@@ -6,19 +8,87 @@ pub const BOARD_HEIGHT: usize = 40; // Full height including hidden rows
 pub const VISIBLE_HEIGHT: usize = 20; // Visible board height
 const FULL_ROW: u32 = 0b11_1111_1111; // All 10 bits set
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+// What filled a cell. Cells filled by `set_cell`, or before colors were
+// tracked, are unknown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CellColor {
+    Piece(PieceType),
+    Garbage,
+    #[default]
+    Unknown,
+}
+
+// By row then column; meaningless for empty cells
+pub type ColorGrid = [[CellColor; BOARD_WIDTH]; BOARD_HEIGHT];
+
+#[derive(Clone, Debug)]
 pub struct Board {
     pub rows: [u32; BOARD_HEIGHT],
+    // Zobrist hash of the filled cells, kept in sync by the mutators below
+    zobrist: u64,
+    // Optional, for display and export only. Boards built for search never
+    // carry one, so copying them stays a plain bitboard copy.
+    colors: Option<Box<ColorGrid>>,
+}
+
+// Colors are cosmetic: boards with the same cells are equal
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.rows == other.rows
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ClearInfo {
+    pub lines_cleared: usize,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Board {
     pub fn new() -> Self {
         Self {
             rows: [0; BOARD_HEIGHT],
+            zobrist: 0,
+            colors: None,
+        }
+    }
+
+    // Start tracking colors; cells already filled are unknown
+    pub fn with_colors(mut self) -> Self {
+        if self.colors.is_none() {
+            self.colors = Some(Box::new([[CellColor::Unknown; BOARD_WIDTH]; BOARD_HEIGHT]));
+        }
+        self
+    }
+
+    // The same cells with no color layer, as search wants them
+    pub fn without_colors(&self) -> Self {
+        Self { rows: self.rows, zobrist: self.zobrist, colors: None }
+    }
+
+    pub fn colors(&self) -> Option<&ColorGrid> {
+        self.colors.as_deref()
+    }
+
+    // None for empty cells and boards without colors
+    pub fn color(&self, x: usize, y: usize) -> Option<CellColor> {
+        if !self.get_cell(x, y) {
+            return None;
+        }
+        self.colors.as_ref()?.get(y)?.get(x).copied()
+    }
+
+    // Ignored for empty cells and boards without colors
+    pub fn set_color(&mut self, x: usize, y: usize, color: CellColor) {
+        if self.get_cell(x, y) {
+            if let Some(cell) = self.colors.as_mut().and_then(|colors| colors.get_mut(y)?.get_mut(x)) {
+                *cell = color;
+            }
         }
     }
 
@@ -36,16 +106,25 @@ impl Board {
         board
     }
 
+    // Inverse of `from_flat_array`: the visible rows, top first, 1 for filled
+    pub fn to_flat_array(&self) -> Vec<i32> {
+        let start_y = BOARD_HEIGHT - VISIBLE_HEIGHT;
+        (start_y..BOARD_HEIGHT)
+            .flat_map(|y| (0..BOARD_WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| self.get_cell(x, y) as i32)
+            .collect()
+    }
+
     pub fn is_full_row(&self, row: usize) -> bool {
         (self.rows[row] & FULL_ROW) == FULL_ROW
     }
 
     pub fn set_cell(&mut self, x: usize, y: usize, filled: bool) {
-        if x < BOARD_WIDTH && y < BOARD_HEIGHT {
-            if filled {
-                self.rows[y] |= 1 << x;
-            } else {
-                self.rows[y] &= !(1 << x);
+        if x < BOARD_WIDTH && y < BOARD_HEIGHT && self.get_cell(x, y) != filled {
+            self.rows[y] ^= 1 << x;
+            self.zobrist ^= CELL_KEYS[y][x];
+            if let Some(colors) = &mut self.colors {
+                colors[y][x] = CellColor::Unknown;
             }
         }
     }
@@ -89,7 +168,20 @@ impl Board {
             for (i, &row_mask) in mask.iter().enumerate() {
                 let board_y = piece.y + i as i32;
                 if board_y >= 0 && board_y < BOARD_HEIGHT as i32 {
-                    self.rows[board_y as usize] |= row_mask as u32;
+                    let y = board_y as usize;
+                    let mut new_cells = row_mask as u32 & !self.rows[y];
+                    self.rows[y] |= new_cells;
+                    while new_cells != 0 {
+                        self.zobrist ^= CELL_KEYS[y][new_cells.trailing_zeros() as usize];
+                        new_cells &= new_cells - 1;
+                    }
+                }
+            }
+            if let Some(colors) = &mut self.colors {
+                for (x, y) in piece.cells() {
+                    if (0..BOARD_WIDTH as i32).contains(&x) && (0..BOARD_HEIGHT as i32).contains(&y) {
+                        colors[y as usize][x as usize] = CellColor::Piece(piece.piece_type);
+                    }
                 }
             }
             true
@@ -99,42 +191,70 @@ impl Board {
     }
 
     pub fn clear_lines(&mut self) -> ClearInfo {
-        let mut _cleared_lines = 0;
+        let mut lines_cleared = 0;
         let mut new_rows = [0u32; BOARD_HEIGHT];
         let mut write_idx = BOARD_HEIGHT - 1;
+        let mut full_rows = 0u64;
 
         // Compact non-full rows from bottom to top
         for read_idx in (0..BOARD_HEIGHT).rev() {
             if !self.is_full_row(read_idx) {
                 new_rows[write_idx] = self.rows[read_idx];
-                if write_idx > 0 {
-                    write_idx -= 1;
-                }
+                write_idx = write_idx.saturating_sub(1);
             } else {
-                _cleared_lines += 1;
+                lines_cleared += 1;
+                full_rows |= 1 << read_idx;
             }
         }
 
-        self.rows = new_rows;
-        
-        ClearInfo {}
+        if lines_cleared > 0 {
+            // Every row above a clear moves, so rehash from scratch
+            self.rows = new_rows;
+            self.zobrist = zobrist::board_hash(&self.rows);
+            if let Some(colors) = &mut self.colors {
+                let mut write = BOARD_HEIGHT;
+                for read in (0..BOARD_HEIGHT).rev().filter(|y| full_rows & (1 << y) == 0) {
+                    write -= 1;
+                    colors[write] = colors[read];
+                }
+                colors[..write].fill([CellColor::Unknown; BOARD_WIDTH]);
+            }
+        }
+
+        ClearInfo { lines_cleared }
     }
 
-    pub fn hash(&self) -> u64 {
-        let mut hash = 0u64;
-        for (i, &row) in self.rows.iter().enumerate() {
-            hash = hash.wrapping_mul(31).wrapping_add(row as u64).wrapping_add(i as u64);
+    // Push the stack up `lines` rows and fill the bottom with garbage open at
+    // column `hole`; false if that pushed filled cells off the top, or, leaving
+    // the board as it was, if `hole` is off the board
+    pub fn add_garbage(&mut self, lines: usize, hole: usize) -> bool {
+        if hole >= BOARD_WIDTH {
+            return false;
         }
-        hash
+        let lines = lines.min(BOARD_HEIGHT);
+        let overflow = self.rows[..lines].iter().any(|&row| row != 0);
+        let garbage = FULL_ROW & !(1 << hole);
+        self.rows.copy_within(lines.., 0);
+        self.rows[BOARD_HEIGHT - lines..].fill(garbage);
+        self.zobrist = zobrist::board_hash(&self.rows);
+        if let Some(colors) = &mut self.colors {
+            colors.copy_within(lines.., 0);
+            colors[BOARD_HEIGHT - lines..].fill([CellColor::Garbage; BOARD_WIDTH]);
+        }
+        !overflow
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.zobrist
     }
 
     // Get column heights for evaluation
     pub fn get_heights(&self) -> [usize; BOARD_WIDTH] {
         let mut heights = [0; BOARD_WIDTH];
-        for x in 0..BOARD_WIDTH {
+        for (x, height) in heights.iter_mut().enumerate() {
             for y in 0..BOARD_HEIGHT {
                 if self.get_cell(x, y) {
-                    heights[x] = BOARD_HEIGHT - y;
+                    *height = BOARD_HEIGHT - y;
                     break;
                 }
             }
@@ -150,8 +270,7 @@ impl Board {
         let max_height = *heights.iter().max().unwrap_or(&0) as f64;
 
         // More efficient hole counting: an empty cell with a block above it.
-        for x in 0..BOARD_WIDTH {
-            let height = heights[x];
+        for (x, &height) in heights.iter().enumerate() {
             if height > 0 {
                 for y in (BOARD_HEIGHT - height)..BOARD_HEIGHT {
                     if !self.get_cell(x, y) {
@@ -171,30 +290,14 @@ impl Board {
 
     // Display board for debugging - shows only visible rows (bottom 20)
     pub fn display_board(&self, title: &str, original_board: Option<&Board>) {
+        let mut options = RenderOptions::default().with_charset(Charset::Unicode);
+        if let Some(original) = original_board {
+            options = options.with_previous(original);
+        }
+
         crate::console_log!("📋 {}", title);
-        
-        // Only show the bottom 20 rows (visible area)
-        let start_row = BOARD_HEIGHT - VISIBLE_HEIGHT;
-        
-        for y in start_row..BOARD_HEIGHT {
-            let mut row_str = String::new();
-            for x in 0..BOARD_WIDTH {
-                let is_new_cell = if let Some(original) = original_board {
-                    self.get_cell(x, y) && !original.get_cell(x, y)
-                } else {
-                    false
-                };
-
-                if is_new_cell {
-                    row_str.push('▓'); // New piece block
-                } else if self.get_cell(x, y) {
-                    row_str.push('█'); // Existing block
-                } else {
-                    row_str.push('·'); // Empty
-                }
-            }
-            let visible_row = y - start_row;
-            crate::console_log!("Row {:2}: {}", visible_row, row_str);
+        for (row, line) in self.render(&options).lines().enumerate() {
+            crate::console_log!("Row {:2}: {}", row, line);
         }
         crate::console_log!(""); // Empty line for separation
     }
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use crate::board::Board;
+use crate::evaluation::EvaluationWeights;
+use crate::movegen::{play_inputs, MoveMap};
+use crate::pieces::{PieceType, Placement};
+use crate::search::{placement_cells, RankedPlacement, SearchEngine, SearchState};
+
+// Largest value gap, in evaluation points, for each grade below Best. With the
+// built-in strategies a new hole costs 8 to 15 points.
+const GOOD_GAP: f64 = 1.5;
+const INACCURACY_GAP: f64 = 4.0;
+const MISTAKE_GAP: f64 = 10.0;
+// Values this close count as a tie with the best placement
+const TIE_EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Severity {
+    pub fn from_gap(gap: f64) -> Self {
+        match gap {
+            gap if gap <= TIE_EPSILON => Severity::Best,
+            gap if gap <= GOOD_GAP => Severity::Good,
+            gap if gap <= INACCURACY_GAP => Severity::Inaccuracy,
+            gap if gap <= MISTAKE_GAP => Severity::Mistake,
+            _ => Severity::Blunder,
+        }
+    }
+}
+
+// How a human placement compares with every other reachable placement.
+// `rank` is 1 for the best; placements with equal value share a rank, and
+// rotations covering the same cells count once.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlacementGrade {
+    pub placement: Placement,
+    pub rank: usize,
+    pub candidates: usize,
+    pub value: f64,
+    // Value lost against the engine's choice; never negative
+    pub gap: f64,
+    pub severity: Severity,
+    pub best: RankedPlacement,
+}
+
+impl SearchEngine {
+    // Grades `placement` with the same lookahead the beam search uses, run on
+    // every distinct placement rather than only the beam. None if the piece
+    // cannot come to rest there.
+    #[allow(clippy::too_many_arguments)]
+    pub fn grade_placement(&self, board: &Board, piece_type: PieceType, placement: &Placement, queue: &[PieceType], weights: &EvaluationWeights, arr: u32, das: u32) -> Option<PlacementGrade> {
+        let played = placement_cells(piece_type, placement);
+        let candidates = self.distinct_placements(board, piece_type, weights);
+        if !candidates.iter().any(|(candidate, _)| placement_cells(piece_type, candidate) == played) {
+            return None;
+        }
+
+        let ranked = self.lookahead(&SearchState::new(board.without_colors()), candidates, queue, weights);
+        let value = ranked.iter().find(|(candidate, ..)| placement_cells(piece_type, candidate) == played)?.2;
+        let rank = 1 + ranked.iter().filter(|(.., other)| *other > value + TIE_EPSILON).count();
+        let candidates = ranked.len();
+
+        let (best, eval, best_value) = ranked.into_iter().next()?;
+        let gap = (best_value - value).max(0.0);
+        Some(PlacementGrade {
+            placement: *placement,
+            rank,
+            candidates,
+            value,
+            gap,
+            severity: Severity::from_gap(gap),
+            best: self.ranked_placement(board, piece_type, best, eval, best_value, arr, das),
+        })
+    }
+}
+
+// A human input sequence against the fewest key presses that lock the piece
+// in the same cells. A DAS charge to the wall (ARR 0 only) is one press.
+#[derive(Clone, Debug, Serialize)]
+pub struct FinesseReport {
+    pub placement: Placement,
+    pub inputs: usize,
+    pub optimal_inputs: usize,
+    pub extra_inputs: usize,
+    pub optimal: Vec<String>,
+}
+
+impl FinesseReport {
+    pub fn is_fault(&self) -> bool {
+        self.extra_inputs > 0
+    }
+}
+
+// Running totals over a game
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct FinesseStats {
+    pub pieces: usize,
+    pub faults: usize,
+    pub inputs: usize,
+    pub extra_inputs: usize,
+}
+
+impl FinesseStats {
+    pub fn record(&mut self, report: &FinesseReport) {
+        self.pieces += 1;
+        self.faults += report.is_fault() as usize;
+        self.inputs += report.inputs;
+        self.extra_inputs += report.extra_inputs;
+    }
+
+    // Share of pieces placed without extra inputs
+    pub fn accuracy(&self) -> f64 {
+        if self.pieces == 0 { 1.0 } else { 1.0 - self.faults as f64 / self.pieces as f64 }
+    }
+}
+
+// Plays `inputs` from spawn and compares them with the pathfinder's shortest
+// route to the same cells; any rotation covering them will do. Blocked inputs
+// still count. None if `play_inputs` cannot play the sequence.
+pub fn check_finesse(board: &Board, piece_type: PieceType, inputs: &[String], arr: u32) -> Option<FinesseReport> {
+    let piece = play_inputs(board, piece_type, inputs)?;
+    let placement = Placement::new(piece.x, piece.y, piece.rotation);
+
+    let played = placement_cells(piece_type, &placement);
+    let moves = MoveMap::new(board, piece_type);
+    let optimal = moves
+        .placements()
+        .into_iter()
+        .filter(|candidate| placement_cells(piece_type, candidate) == played)
+        .filter_map(|candidate| moves.path(&candidate, arr, 1))
+        .min_by_key(|path| path.len())?;
+
+    Some(FinesseReport {
+        placement,
+        inputs: inputs.len(),
+        optimal_inputs: optimal.len(),
+        extra_inputs: inputs.len().saturating_sub(optimal.len()),
+        optimal,
+    })
+}
+
+// Shortest inputs to one resting position on an empty board. `column` is the
+// leftmost column the piece covers. ARR 0 can DAS to a wall in one press;
+// with ARR above 0 every column is its own tap.
+#[derive(Clone, Debug, Serialize)]
+pub struct FinesseEntry {
+    pub piece: PieceType,
+    pub rotation: usize,
+    pub column: i32,
+    pub placement: Placement,
+    pub arr_zero: Vec<String>,
+    pub arr_nonzero: Vec<String>,
+}
+
+// Every piece and resting spot on an empty board, by piece, rotation and
+// column. Rotations that cover the same cells are listed once, under the one
+// with the fewest inputs (the lowest on a tie), as `distinct_placements` does.
+pub fn finesse_table() -> Vec<FinesseEntry> {
+    let board = Board::new();
+    let mut table = Vec::new();
+    for piece in PieceType::ALL {
+        let moves = MoveMap::new(&board, piece);
+        let mut placements = moves.placements();
+        placements.sort_by_key(|placement| placement.rotation);
+        let mut entries: HashMap<[(i32, i32); 4], FinesseEntry> = HashMap::new();
+        for placement in placements {
+            let cells = placement_cells(piece, &placement);
+            let (Some(arr_zero), Some(arr_nonzero)) = (moves.path(&placement, 0, 1), moves.path(&placement, 1, 1)) else {
+                continue;
+            };
+            let entry = FinesseEntry { piece, rotation: placement.rotation, column: cells[0].0, placement, arr_zero, arr_nonzero };
+            let inputs = |entry: &FinesseEntry| (entry.arr_zero.len(), entry.arr_nonzero.len());
+            if entries.get(&cells).is_none_or(|kept| inputs(&entry) < inputs(kept)) {
+                entries.insert(cells, entry);
+            }
+        }
+        let mut entries: Vec<FinesseEntry> = entries.into_values().collect();
+        entries.sort_by_key(|entry| (entry.rotation, entry.column));
+        table.extend(entries);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finesse_paths_lock_where_listed() {
+        let board = Board::new();
+        for entry in finesse_table() {
+            for path in [&entry.arr_zero, &entry.arr_nonzero] {
+                let locked = play_inputs(&board, entry.piece, path).map(|piece| Placement::new(piece.x, piece.y, piece.rotation));
+                assert_eq!(locked, Some(entry.placement), "{:?} rotation {} column {}: {:?}", entry.piece, entry.rotation, entry.column, path);
+            }
+        }
+    }
+
+    #[test]
+    fn finesse_input_counts() {
+        let table = finesse_table();
+        // Inputs under ARR 0 and ARR above 0, the hard drop included
+        let inputs = |piece: PieceType, rotation: usize, column: i32| {
+            table.iter()
+                .find(|entry| (entry.piece, entry.rotation, entry.column) == (piece, rotation, column))
+                .map(|entry| (entry.arr_zero.len(), entry.arr_nonzero.len()))
+        };
+        assert_eq!(inputs(PieceType::O, 0, 4), Some((1, 1)));
+        assert_eq!(inputs(PieceType::O, 0, 0), Some((2, 5)));
+        assert_eq!(inputs(PieceType::O, 0, 8), Some((2, 5)));
+        assert_eq!(inputs(PieceType::I, 0, 3), Some((1, 1)));
+        assert_eq!(inputs(PieceType::I, 0, 0), Some((2, 4)));
+        assert_eq!(inputs(PieceType::I, 0, 6), Some((2, 4)));
+        assert_eq!(inputs(PieceType::T, 3, 0), Some((3, 5)));
+    }
+}
@@ -1,48 +1,147 @@
+use serde::{Deserialize, Serialize};
 use crate::board::Board;
-use crate::pieces::PieceType;
-use crate::search::SearchEngine;
+use crate::pieces::{Piece, PieceType, Placement};
+use crate::search::{RankedPlacement, SearchBackend, SearchEngine};
 use crate::console_log;
-use crate::evaluation::Strategy;
+use crate::evaluation::{Evaluation, EvaluationWeights, SpinKind, Strategy};
+use crate::pc::{PcProbability, PcSolution, PerfectClearSolver};
+use crate::bag::{BagRule, BagState};
+use crate::anytime::SearchBudget;
+use crate::coach::{self, FinesseReport, PlacementGrade};
+use crate::fumen::{self, FumenPage};
+use crate::movegen::{Input, MoveMap};
+use crate::sim::Handling;
+
+// Enough history to pin down the bag phase; trimmed a whole bag at a time
+const PIECE_HISTORY_LIMIT: usize = 28;
+// Search time spent on the next piece during each call while one falls
+pub const DEFAULT_PONDER_MS: f64 = 4.0;
+// Bumped whenever `GameState` changes shape
+pub const GAME_STATE_VERSION: u32 = 1;
+
+// Everything the engine knows about the game it is playing, for snapshots
+// that `load_game_state` restores exactly
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameState {
+    pub version: u32,
+    pub board: Board,
+    // Where the plan has moved the piece so far
+    pub active: Option<Piece>,
+    // Always empty while the bot does not model hold
+    pub hold: Option<PieceType>,
+    pub queue: Vec<PieceType>,
+    pub combo: u32,
+    pub b2b: bool,
+    pub handling: Handling,
+    pub strategy: Strategy,
+    // Move sequence for the active piece and how much of it has been sent
+    pub plan: Vec<String>,
+    pub plan_index: usize,
+    // Recent pieces, from which the bag is inferred
+    pub piece_history: Vec<PieceType>,
+}
 
 pub struct TetrisEngine {
     search_engine: SearchEngine,
     current_move_sequence: Vec<String>,
     sequence_index: usize,
     expected_board: Option<Board>,
-    arr: u32,
-    das: u32,
-    sdf: u32,
-    dcd: u32,
+    piece_history: Vec<PieceType>,
+    // Board hash and piece when a spawn was last seen, so asking about the
+    // same piece again does not count it twice
+    observed: Option<(u64, PieceType)>,
+    ponder_ms: f64,
+    handling: Handling,
     debug: bool,
+    // The position last played from, kept for `game_state`
+    board: Board,
+    current: Option<PieceType>,
+    hold: Option<PieceType>,
+    queue: Vec<PieceType>,
+    strategy: Strategy,
+    // Locks in a row that cleared lines, and whether the last clear was a
+    // tetris or a spin, as far as the engine's own placements show
+    combo: u32,
+    b2b: bool,
+}
+
+impl Default for TetrisEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TetrisEngine {
     pub fn new() -> Self {
+        let mut search_engine = SearchEngine::new();
+        search_engine.set_perfect_clear(Some(PerfectClearSolver::default()));
+
         Self {
-            search_engine: SearchEngine::new(),
+            search_engine,
             current_move_sequence: Vec::new(),
             sequence_index: 0,
             expected_board: None,
-            arr: 16,
-            das: 133,
-            sdf: u32::MAX,
-            dcd: 0,
+            piece_history: Vec::new(),
+            observed: None,
+            ponder_ms: DEFAULT_PONDER_MS,
+            handling: Handling::default(),
             debug: false,
+            board: Board::new(),
+            current: None,
+            hold: None,
+            queue: Vec::new(),
+            strategy: Strategy::Balanced,
+            combo: 0,
+            b2b: false,
         }
     }
 
     pub fn configure_movement(&mut self, arr: u32, das: u32, sdf: u32, dcd: u32) {
-        self.arr = arr;
-        self.das = das;
-        self.sdf = sdf;
-        self.dcd = dcd;
-        console_log!("[Config] Movement settings updated: ARR={}, DAS={}, SDF={}, DCD={}", self.arr, self.das, self.sdf, self.dcd);
+        self.handling = Handling { arr, das, sdf, dcd };
+        console_log!("[Config] Movement settings updated: ARR={}, DAS={}, SDF={}, DCD={}", arr, das, sdf, dcd);
     }
 
     pub fn configure_logging(&mut self, debug: bool) {
         self.debug = debug;
     }
 
+    pub fn configure_lookahead(&mut self, beam_width: usize, chance_depth: usize) {
+        self.search_engine.set_lookahead(beam_width, chance_depth);
+    }
+
+    pub fn configure_mcts(&mut self, enabled: bool, iterations: usize) {
+        let backend = if enabled { SearchBackend::Mcts { iterations } } else { SearchBackend::Beam };
+        self.search_engine.set_backend(backend);
+    }
+
+    // 0 turns pondering off
+    pub fn configure_pondering(&mut self, budget_ms: f64) {
+        self.ponder_ms = budget_ms.max(0.0);
+    }
+
+    pub fn configure_perfect_clear(&mut self, enabled: bool, max_height: usize) {
+        let solver = enabled.then(|| PerfectClearSolver::new(max_height));
+        self.search_engine.set_perfect_clear(solver);
+    }
+
+    pub fn find_perfect_clear(&self, board: &[i32], current_piece: i32, hold_piece: i32, queue: &[i32], max_height: usize) -> Option<PcSolution> {
+        let board_obj = Board::from_flat_array(board);
+        let piece_type = PieceType::from_i32(current_piece)?;
+        let hold_type = PieceType::from_i32(hold_piece);
+        let queue_types: Vec<PieceType> = queue.iter().filter_map(|&p| PieceType::from_i32(p)).collect();
+
+        PerfectClearSolver::new(max_height).solve(&board_obj, piece_type, hold_type, &queue_types)
+    }
+
+    pub fn pc_probability(&self, board: &[i32], hold_piece: i32, queue: &[i32], bag_remaining: &[i32], rule: BagRule, max_height: usize) -> PcProbability {
+        let board_obj = Board::from_flat_array(board);
+        let hold_type = PieceType::from_i32(hold_piece);
+        let known: Vec<PieceType> = queue.iter().filter_map(|&p| PieceType::from_i32(p)).collect();
+        let remaining: Vec<PieceType> = bag_remaining.iter().filter_map(|&p| PieceType::from_i32(p)).collect();
+
+        PerfectClearSolver::new(max_height).probability(&board_obj, hold_type, &known, rule, BagState::from_remaining(&remaining))
+    }
+
     pub fn get_best_move(&mut self, board: &[i32], current_piece: i32, next_piece: i32, strategy: Strategy) -> String {
         let board_obj = Board::from_flat_array(board);
 
@@ -50,6 +149,10 @@ impl TetrisEngine {
         if !self.current_move_sequence.is_empty() && self.sequence_index < self.current_move_sequence.len() {
              if let Some(expected_board) = &self.expected_board {
                 if expected_board.hash() == board_obj.hash() {
+                    // The placement is settled; think about the next piece meanwhile
+                    if self.ponder_ms > 0.0 {
+                        self.search_engine.think(SearchBudget::time(self.ponder_ms));
+                    }
                     let next_move = self.current_move_sequence[self.sequence_index].clone();
                     self.sequence_index += 1;
                     return next_move;
@@ -65,10 +168,18 @@ impl TetrisEngine {
         }
         
         let piece_type = PieceType::from_i32(current_piece).unwrap_or(PieceType::I);
-        let next_piece_type = PieceType::from_i32(next_piece);
+        let queue: Vec<PieceType> = PieceType::from_i32(next_piece).into_iter().collect();
+
+        if self.is_new_spawn(&board_obj, piece_type) {
+            self.observe_piece(piece_type);
+            self.settle_lock(&board_obj);
+        }
+        self.observed = Some((board_obj.hash(), piece_type));
+        self.update_bag(&queue);
+        self.set_position(&board_obj, piece_type, &queue, strategy);
 
-        // Generate new move sequence
-        let search_result = self.search_engine.search(&board_obj, piece_type, next_piece_type, strategy, self.arr, self.das, self.debug);
+        // Generate new move sequence, reusing the tree from the last piece
+        let search_result = self.search_engine.search_with_tree(&board_obj, piece_type, &queue, strategy, self.handling.arr, self.handling.das, self.debug);
         self.current_move_sequence = search_result.best_move.split(',').map(String::from).collect();
         self.sequence_index = 0;
 
@@ -87,13 +198,277 @@ impl TetrisEngine {
     pub fn get_full_move_sequence(&mut self, board: &[i32], current_piece_idx: i32, next_piece_idx: i32, strategy: Strategy) -> String {
         let board_obj = Board::from_flat_array(board);
         let piece_type = PieceType::from_i32(current_piece_idx).unwrap_or(PieceType::I);
-        let next_piece_type = PieceType::from_i32(next_piece_idx);
+        let queue: Vec<PieceType> = PieceType::from_i32(next_piece_idx).into_iter().collect();
+        self.update_bag(&queue);
 
-        let search_result = self.search_engine.search(&board_obj, piece_type, next_piece_type, strategy, self.arr, self.das, true); // Debug is true for this function
+        let search_result = self.search_engine.search(&board_obj, piece_type, &queue, strategy, self.handling.arr, self.handling.das, true); // Debug is true for this function
         search_result.best_move
     }
 
+    pub fn top_placements(&mut self, board: &[i32], current_piece: i32, next_piece: i32, strategy: Strategy, count: usize) -> Vec<RankedPlacement> {
+        let board_obj = Board::from_flat_array(board);
+        let Some(piece_type) = PieceType::from_i32(current_piece) else {
+            return Vec::new();
+        };
+        let queue: Vec<PieceType> = PieceType::from_i32(next_piece).into_iter().collect();
+        self.update_bag(&queue);
+
+        let weights = EvaluationWeights::new(strategy);
+        self.search_engine.top_placements(&board_obj, piece_type, &queue, &weights, count, self.handling.arr, self.handling.das)
+    }
+
+    // The same candidates as a fumen, one unlocked page each, best first
+    pub fn top_placements_fumen(&mut self, board: &[i32], current_piece: i32, next_piece: i32, strategy: Strategy, count: usize) -> String {
+        let board_obj = Board::from_flat_array(board);
+        let Some(piece_type) = PieceType::from_i32(current_piece) else {
+            return fumen::encode(&[FumenPage::new(board_obj)]);
+        };
+        let pages: Vec<FumenPage> = self
+            .top_placements(board, current_piece, next_piece, strategy, count)
+            .iter()
+            .enumerate()
+            .map(|(rank, candidate)| {
+                FumenPage::new(board_obj.clone())
+                    .with_piece(piece_type, candidate.placement)
+                    .with_comment(&format!("{}. value {:.2}", rank + 1, candidate.value))
+                    .with_lock(false)
+            })
+            .collect();
+        fumen::encode(&pages)
+    }
+
+    // Grade the placement a human locked, given the board just before the lock
+    pub fn grade_placement(&mut self, board: &[i32], current_piece: i32, next_piece: i32, placement: Placement, strategy: Strategy) -> Option<PlacementGrade> {
+        let board_obj = Board::from_flat_array(board);
+        let piece_type = PieceType::from_i32(current_piece)?;
+        let queue: Vec<PieceType> = PieceType::from_i32(next_piece).into_iter().collect();
+        self.update_bag(&queue);
+
+        let weights = EvaluationWeights::new(strategy);
+        self.search_engine.grade_placement(&board_obj, piece_type, &placement, &queue, &weights, self.handling.arr, self.handling.das)
+    }
+
+    // Finesse of the inputs a human used for one piece, under the configured ARR
+    pub fn check_finesse(&self, board: &[i32], current_piece: i32, inputs: &[String]) -> Option<FinesseReport> {
+        let board_obj = Board::from_flat_array(board);
+        let piece_type = PieceType::from_i32(current_piece)?;
+        coach::check_finesse(&board_obj, piece_type, inputs, self.handling.arr)
+    }
+
+    // Why `placement` scores what it does; None if the piece cannot rest there
+    pub fn explain_placement(&self, board: &[i32], current_piece: i32, placement: Placement, strategy: Strategy) -> Option<Evaluation> {
+        let board_obj = Board::from_flat_array(board);
+        let piece_type = PieceType::from_i32(current_piece)?;
+        self.search_engine.explain_placement(&board_obj, piece_type, &placement, &EvaluationWeights::new(strategy))
+    }
+
+    // Begin an anytime search for a newly spawned piece; drive it with `think`.
+    // The tree from the previous piece is reused when the board followed one
+    // of its placements.
+    pub fn start_thinking(&mut self, board: &[i32], current_piece: i32, next_piece: i32, strategy: Strategy) {
+        let board_obj = Board::from_flat_array(board);
+        let piece_type = PieceType::from_i32(current_piece).unwrap_or(PieceType::I);
+        let queue: Vec<PieceType> = PieceType::from_i32(next_piece).into_iter().collect();
+
+        if self.is_new_spawn(&board_obj, piece_type) {
+            self.observe_piece(piece_type);
+            self.settle_lock(&board_obj);
+        }
+        self.observed = Some((board_obj.hash(), piece_type));
+        self.update_bag(&queue);
+        self.set_position(&board_obj, piece_type, &queue, strategy);
+        // The host plays whatever it polls, so there is no plan to follow
+        self.current_move_sequence.clear();
+        self.sequence_index = 0;
+        self.expected_board = None;
+
+        let weights = EvaluationWeights::new(strategy);
+        self.search_engine.begin_tree(&board_obj, piece_type, &queue, weights, false);
+    }
+
+    // Returns true once the search has nothing left to deepen
+    pub fn think(&mut self, budget: SearchBudget) -> bool {
+        self.search_engine.think(budget)
+    }
+
+    // Move sequence for the best placement found so far, if a search is running
+    pub fn poll(&self) -> Option<String> {
+        let search = self.search_engine.tree()?;
+        let piece = search.piece()?;
+        let best = search.best()?;
+        if self.debug {
+            console_log!("🧠 Best so far: x={}, rot={} → {:.1} (level {})", best.placement.x, best.placement.rotation, best.value, best.level);
+        }
+        let move_sequence = self.search_engine.generate_move_sequence(search.board(), piece, &best.placement, self.handling.arr, self.handling.das);
+        Some(move_sequence.join(","))
+    }
+
+    // Snapshot of the position and plan, for sharing or reloading
+    pub fn game_state(&self) -> GameState {
+        let active = self.current.map(|piece_type| {
+            let sent = &self.current_move_sequence[..self.sequence_index];
+            sent.iter().filter_map(|action| Input::from_action(action)).fold(Piece::spawn(piece_type), |piece, input| input.apply(&self.board, &piece).unwrap_or(piece))
+        });
+        GameState {
+            version: GAME_STATE_VERSION,
+            board: self.board.clone(),
+            active,
+            hold: self.hold,
+            queue: self.queue.clone(),
+            combo: self.combo,
+            b2b: self.b2b,
+            handling: self.handling,
+            strategy: self.strategy,
+            plan: self.current_move_sequence.clone(),
+            plan_index: self.sequence_index,
+            piece_history: self.piece_history.clone(),
+        }
+    }
+
+    // Restore a snapshot; `get_best_move` on the same board carries on with
+    // the plan from where it was
+    pub fn load_game_state(&mut self, state: GameState) -> Result<(), String> {
+        if state.version != GAME_STATE_VERSION {
+            return Err(format!("game state version {} is not supported (expected {})", state.version, GAME_STATE_VERSION));
+        }
+        if state.plan_index > state.plan.len() {
+            return Err(format!("plan index {} is past the end of a {}-move plan", state.plan_index, state.plan.len()));
+        }
+        self.expected_board = (!state.plan.is_empty()).then(|| state.board.without_colors());
+        self.observed = state.active.map(|piece| (state.board.hash(), piece.piece_type));
+        self.board = state.board;
+        self.current = state.active.map(|piece| piece.piece_type);
+        self.hold = state.hold;
+        self.combo = state.combo;
+        self.b2b = state.b2b;
+        self.handling = state.handling;
+        self.strategy = state.strategy;
+        self.current_move_sequence = state.plan;
+        self.sequence_index = state.plan_index;
+        self.piece_history = state.piece_history;
+        self.update_bag(&state.queue);
+        self.queue = state.queue;
+        Ok(())
+    }
+
+    fn set_position(&mut self, board: &Board, piece_type: PieceType, queue: &[PieceType], strategy: Strategy) {
+        self.board = board.clone();
+        self.current = Some(piece_type);
+        self.queue = queue.to_vec();
+        self.strategy = strategy;
+    }
+
+    // When a new piece spawns: find where the last one locked to turn `board`
+    // into what it is, and carry combo and back-to-back over from that lock.
+    // The combo drops if no placement explains the board.
+    fn settle_lock(&mut self, board: &Board) {
+        let outcome = self.current.and_then(|piece_type| {
+            let moves = MoveMap::new(&self.board, piece_type);
+            moves.placements().into_iter().find_map(|placement| {
+                let mut after = self.board.without_colors();
+                after.lock_piece(&Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation));
+                let lines = after.clear_lines().lines_cleared;
+                (after == *board).then(|| (lines, moves.spin(&placement)))
+            })
+        });
+        match outcome {
+            Some((0, _)) | None => self.combo = 0,
+            Some((lines, spin)) => {
+                self.combo += 1;
+                self.b2b = lines >= 4 || spin != SpinKind::None;
+            }
+        }
+    }
+
+    // A search is for a new piece unless it is the one last seen: on the same
+    // board, or on a changed one (rising garbage) before its plan was sent
+    fn is_new_spawn(&self, board: &Board, piece_type: PieceType) -> bool {
+        match self.observed {
+            Some((_, piece)) if piece != piece_type => true,
+            Some((hash, _)) if hash == board.hash() => false,
+            Some(_) => self.sequence_index >= self.current_move_sequence.len(),
+            None => true,
+        }
+    }
+
+    fn observe_piece(&mut self, piece_type: PieceType) {
+        self.piece_history.push(piece_type);
+        if self.piece_history.len() > PIECE_HISTORY_LIMIT {
+            self.piece_history.drain(..7);
+        }
+    }
+
+    // Bag state after the preview, inferred from every piece seen so far
+    fn update_bag(&mut self, queue: &[PieceType]) {
+        let mut observed = self.piece_history.clone();
+        observed.extend_from_slice(queue);
+        self.search_engine.set_bag(BagRule::SevenBag, BagState::infer(&observed));
+    }
+
     fn calculate_final_board(&self, board: &Board, _piece_type: PieceType, _sequence: &Vec<String>) -> Board {
         board.clone()
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Combo and back-to-back after the engine sees `after` spawn `next`, having
+    // last been asked about `piece` on `before`
+    fn settle(before: &str, piece: PieceType, after: &str, combo: u32, b2b: bool) -> (u32, bool) {
+        let mut engine = TetrisEngine::new();
+        let state = GameState {
+            board: before.parse().unwrap(),
+            active: Some(Piece::spawn(piece)),
+            queue: vec![PieceType::O],
+            combo,
+            b2b,
+            ..engine.game_state()
+        };
+        engine.load_game_state(state).unwrap();
+        let after: Board = after.parse().unwrap();
+        engine.get_best_move(&after.to_flat_array(), PieceType::O as i32, PieceType::I as i32, Strategy::Balanced);
+        let state = engine.game_state();
+        (state.combo, state.b2b)
+    }
+
+    #[test]
+    fn spin_clear_carries_combo_and_back_to_back() {
+        // A T-spin double: the T only gets under the overhang by rotating
+        let slot = "#..#######\n#...######\n##.#######";
+        assert_eq!(settle(slot, PieceType::T, "#..#######", 1, false), (2, true));
+        // A plain single ends back-to-back but keeps the combo going
+        assert_eq!(settle("#########.", PieceType::I, ".........#\n.........#\n.........#", 2, true), (3, false));
+        // No clear ends the combo and leaves back-to-back alone
+        assert_eq!(settle("", PieceType::O, "....OO....\n....OO....", 3, true), (0, true));
+    }
+
+    #[test]
+    fn game_state_round_trips() {
+        let mut engine = TetrisEngine::new();
+        let board = Board::new().to_flat_array();
+        engine.get_best_move(&board, PieceType::T as i32, PieceType::O as i32, Strategy::Balanced);
+        let state = engine.game_state();
+        assert!(!state.plan.is_empty());
+
+        let json = serde_json::to_string(&state).unwrap();
+        let back: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, state);
+
+        let mut restored = TetrisEngine::new();
+        restored.load_game_state(back).unwrap();
+        assert_eq!(restored.game_state(), state);
+        // Both carry on with the same plan
+        let next = |engine: &mut TetrisEngine| engine.get_best_move(&board, PieceType::T as i32, PieceType::O as i32, Strategy::Balanced);
+        assert_eq!(next(&mut restored), next(&mut engine));
+    }
+
+    #[test]
+    fn rejects_unknown_game_states() {
+        let mut engine = TetrisEngine::new();
+        let state = engine.game_state();
+        assert!(engine.load_game_state(GameState { version: GAME_STATE_VERSION + 1, ..state.clone() }).is_err());
+        assert!(engine.load_game_state(GameState { plan: vec!["hard_drop".to_string()], plan_index: 2, ..state.clone() }).is_err());
+        assert_eq!(engine.load_game_state(state), Ok(()));
+    }
+}
@@ -1,8 +1,11 @@
 use crate::board::{Board};
+use crate::zobrist;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-#[derive(Clone, Debug, Copy, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Strategy {
     Balanced,
     Aggressive,
@@ -10,9 +13,73 @@ pub enum Strategy {
     TSpan,
 }
 
-#[derive(Clone, Debug, Default)]
+impl Strategy {
+    pub const ALL: [Strategy; 4] = [Strategy::Balanced, Strategy::Aggressive, Strategy::Defensive, Strategy::TSpan];
+
+    // Lowercase name as given on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Balanced => "balanced",
+            Strategy::Aggressive => "aggressive",
+            Strategy::Defensive => "defensive",
+            Strategy::TSpan => "tspan",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Strategy::ALL.into_iter().find(|strategy| strategy.name() == name)
+    }
+}
+
+// How a placement locked. T pieces follow the three-corner rule; other
+// pieces that rotated into a spot they cannot move out of count as mini.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize)]
+pub enum SpinKind {
+    #[default]
+    None,
+    Mini,
+    Full,
+}
+
+// One board feature: its raw value, the weight applied and the product
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct FeatureScore {
+    pub value: f64,
+    pub weight: f64,
+    pub contribution: f64,
+}
+
+impl FeatureScore {
+    fn new(value: f64, weight: f64) -> Self {
+        Self { value, weight, contribution: value * weight }
+    }
+}
+
+// `score` is the sum of every contribution and reward below. Rewards are
+// zero when a bare board is evaluated; see `with_rewards`.
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct Evaluation {
     pub score: f64,
+    pub aggregate_height: FeatureScore,
+    pub max_height: FeatureScore,
+    pub bumpiness: FeatureScore,
+    pub holes: FeatureScore,
+    pub lines_cleared: usize,
+    pub spin: SpinKind,
+    pub line_clear_reward: f64,
+    pub spin_reward: f64,
+}
+
+impl Evaluation {
+    // Add what the placement itself earned: the lines it cleared and its spin
+    pub fn with_rewards(mut self, lines_cleared: usize, spin: SpinKind, weights: &EvaluationWeights) -> Self {
+        self.lines_cleared = lines_cleared;
+        self.spin = spin;
+        self.line_clear_reward = weights.line_clear_reward(lines_cleared);
+        self.spin_reward = weights.spin_reward(lines_cleared, spin);
+        self.score += self.line_clear_reward + self.spin_reward;
+        self
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -21,9 +88,20 @@ pub struct EvaluationWeights {
     pub max_height: f64,
     pub bumpiness: f64,
     pub holes: f64,
+    // Reward by lines cleared, indexed by line count
+    pub line_clear: [f64; 5],
+    // Extra reward for spins by lines cleared (zero to three)
+    pub spin: [f64; 4],
+    pub mini_spin: [f64; 4],
 }
 
+// Default weights only judge the board left behind
+const NO_CLEAR_REWARD: [f64; 5] = [0.0; 5];
+const NO_SPIN_REWARD: [f64; 4] = [0.0; 4];
+
 impl EvaluationWeights {
+    // Line-clear rewards go by lines cleared: aggressive and T-spin play hold
+    // out for tetrises, defensive play takes any clear it can get
     pub fn new(strategy: Strategy) -> Self {
         match strategy {
             Strategy::Balanced => Self {
@@ -31,27 +109,63 @@ impl EvaluationWeights {
                 max_height: -0.5,
                 bumpiness: -0.3,
                 holes: -10.0,
+                line_clear: [0.0, 0.0, 0.5, 1.5, 6.0],
+                spin: NO_SPIN_REWARD,
+                mini_spin: NO_SPIN_REWARD,
             },
             Strategy::Aggressive => Self {
                 aggregate_height: -0.1,
                 max_height: -0.3,
                 bumpiness: -0.2,
                 holes: -8.0,
+                line_clear: [0.0, -1.5, -0.5, 1.0, 12.0],
+                spin: NO_SPIN_REWARD,
+                mini_spin: NO_SPIN_REWARD,
             },
             Strategy::Defensive => Self {
                 aggregate_height: -0.8,
                 max_height: -2.0,
                 bumpiness: -0.5,
                 holes: -15.0,
+                line_clear: [0.0, 1.5, 3.0, 4.5, 6.0],
+                spin: NO_SPIN_REWARD,
+                mini_spin: NO_SPIN_REWARD,
             },
             Strategy::TSpan => Self {
                 aggregate_height: -0.3,
                 max_height: -0.8,
                 bumpiness: -0.25,
                 holes: -12.0,
+                line_clear: [0.0, -1.0, -0.5, 0.5, 6.0],
+                spin: [0.5, 6.0, 12.0, 16.0],
+                mini_spin: [0.0, 1.0, 2.0, 2.0],
             },
         }
     }
+
+    pub fn line_clear_reward(&self, lines_cleared: usize) -> f64 {
+        self.line_clear[lines_cleared.min(4)]
+    }
+
+    pub fn spin_reward(&self, lines_cleared: usize, spin: SpinKind) -> f64 {
+        match spin {
+            SpinKind::None => 0.0,
+            SpinKind::Mini => self.mini_spin[lines_cleared.min(3)],
+            SpinKind::Full => self.spin[lines_cleared.min(3)],
+        }
+    }
+}
+
+impl EvaluationWeights {
+    // Tells weight sets apart in cached search values
+    pub fn fingerprint(&self) -> u64 {
+        [self.aggregate_height, self.max_height, self.bumpiness, self.holes]
+            .iter()
+            .chain(&self.line_clear)
+            .chain(&self.spin)
+            .chain(&self.mini_spin)
+            .fold(0, |hash, weight| zobrist::mix(hash ^ weight.to_bits()))
+    }
 }
 
 impl Default for EvaluationWeights {
@@ -61,6 +175,9 @@ impl Default for EvaluationWeights {
             max_height: -1.76,
             bumpiness: -0.18,
             holes: -0.36,
+            line_clear: NO_CLEAR_REWARD,
+            spin: NO_SPIN_REWARD,
+            mini_spin: NO_SPIN_REWARD,
         }
     }
 }
@@ -68,15 +185,19 @@ impl Default for EvaluationWeights {
 impl Board {
     pub fn evaluate(&self, weights: &EvaluationWeights) -> Evaluation {
         let (total_height, max_height, holes, bumpiness) = self.get_evaluation_metrics();
-        
-        let score =
-            total_height * weights.aggregate_height
-                + max_height * weights.max_height
-                + holes * weights.holes
-                + bumpiness * weights.bumpiness;
+
+        let aggregate_height = FeatureScore::new(total_height, weights.aggregate_height);
+        let max_height = FeatureScore::new(max_height, weights.max_height);
+        let holes = FeatureScore::new(holes, weights.holes);
+        let bumpiness = FeatureScore::new(bumpiness, weights.bumpiness);
 
         Evaluation {
-            score,
+            score: aggregate_height.contribution + max_height.contribution + holes.contribution + bumpiness.contribution,
+            aggregate_height,
+            max_height,
+            bumpiness,
+            holes,
+            ..Evaluation::default()
         }
     }
-} 
\ No newline at end of file
+}
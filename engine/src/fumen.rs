@@ -0,0 +1,540 @@
+use std::fmt;
+use crate::board::{Board, CellColor, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::parse;
+use crate::pieces::{Piece, PieceType, Placement};
+use crate::render::piece_letter;
+use crate::sim::PlacementRecord;
+
+// Fumen v115, the format of fumen.zui.jp and most Tetris tooling: 23 rows of
+// field plus a garbage row below them, each page stored as a run-length diff
+// against the field the previous page left behind.
+const ENCODE_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const FIELD_TOP: usize = 23;
+const FIELD_ROWS: usize = FIELD_TOP + 1;
+const FIELD_CELLS: u32 = (FIELD_ROWS * BOARD_WIDTH) as u32;
+// Engine row of the top fumen row
+const ROW_OFFSET: usize = BOARD_HEIGHT - FIELD_TOP;
+const GRAY: u8 = 8;
+// Comments are escaped to printable ASCII and packed four to five digits
+const COMMENT_BASE: u32 = 96;
+const MAX_COMMENT: usize = 4095;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FumenError {
+    // Not a v115 fumen
+    Version,
+    BadChar(char),
+    // Data ended in the middle of a page
+    Truncated,
+    // A field diff or piece that no editor writes
+    Corrupt,
+}
+
+impl fmt::Display for FumenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FumenError::Version => write!(f, "not a v115 fumen"),
+            FumenError::BadChar(c) => write!(f, "unexpected {:?} in fumen data", c),
+            FumenError::Truncated => write!(f, "fumen data ends mid-page"),
+            FumenError::Corrupt => write!(f, "fumen data is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for FumenError {}
+
+// One page: the field as shown, before `piece` locks. Filled cells take
+// their color from the board's color layer and are gray without one.
+#[derive(Clone, Debug)]
+pub struct FumenPage {
+    pub board: Board,
+    // In engine coordinates; None for a page without a piece
+    pub piece: Option<PlacementRecord>,
+    pub comment: String,
+    // Lock `piece` and clear lines before the next page
+    pub lock: bool,
+}
+
+impl FumenPage {
+    pub fn new(board: Board) -> Self {
+        Self { board, piece: None, comment: String::new(), lock: true }
+    }
+
+    pub fn with_piece(self, piece: PieceType, placement: Placement) -> Self {
+        Self { piece: Some(PlacementRecord { piece, placement, hold: false }), ..self }
+    }
+
+    pub fn with_comment(self, comment: &str) -> Self {
+        Self { comment: comment.to_string(), ..self }
+    }
+
+    pub fn with_lock(self, lock: bool) -> Self {
+        Self { lock, ..self }
+    }
+
+    // Hold, current and next pieces if the comment carries them
+    pub fn queue(&self) -> Option<QueueAnnotation> {
+        QueueAnnotation::from_comment(&self.comment)
+    }
+}
+
+// The quiz comment fumen editors use for piece queues: `#Q=[H](C)NEXT`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueAnnotation {
+    pub hold: Option<PieceType>,
+    pub current: Option<PieceType>,
+    pub next: Vec<PieceType>,
+}
+
+impl QueueAnnotation {
+    pub fn from_comment(comment: &str) -> Option<Self> {
+        let rest = comment.strip_prefix("#Q=[")?;
+        let (hold, rest) = rest.split_once("](")?;
+        let (current, next) = rest.split_once(')')?;
+        let piece = |letters: &str| match letters.len() {
+            0 => Some(None),
+            1 => parse::piece_from_letter(letters.chars().next()?).map(Some),
+            _ => None,
+        };
+        Some(Self {
+            hold: piece(hold)?,
+            current: piece(current)?,
+            next: next.chars().map_while(parse::piece_from_letter).collect(),
+        })
+    }
+
+    pub fn to_comment(&self) -> String {
+        let letter = |piece: Option<PieceType>| piece.map(piece_letter).map(String::from).unwrap_or_default();
+        let next: String = self.next.iter().map(|&piece| piece_letter(piece)).collect();
+        format!("#Q=[{}]({}){}", letter(self.hold), letter(self.current), next)
+    }
+}
+
+// Fumen numbers pieces I, L, O, Z, T, J, S from 1, with 8 for garbage
+fn piece_code(piece: PieceType) -> u8 {
+    [1, 3, 5, 7, 4, 6, 2][piece as usize]
+}
+
+fn code_piece(code: u8) -> Option<PieceType> {
+    match code {
+        1 => Some(PieceType::I),
+        2 => Some(PieceType::L),
+        3 => Some(PieceType::O),
+        4 => Some(PieceType::Z),
+        5 => Some(PieceType::T),
+        6 => Some(PieceType::J),
+        7 => Some(PieceType::S),
+        _ => None,
+    }
+}
+
+// Fumen rotations run south, east, north, west
+fn rotation_code(rotation: usize) -> u32 {
+    [2, 1, 0, 3][rotation % 4]
+}
+
+// Fumen stores some pieces by a different cell than their SRS centre, so
+// that each piece keeps one reference cell through 180 degrees (O through
+// all four rotations); the offset from the centre, y up
+fn stored_offset(piece: PieceType, rotation: usize) -> (i32, i32) {
+    match (piece, rotation % 4) {
+        (PieceType::O, 0) => (0, 1),
+        (PieceType::O, 2) => (-1, 0),
+        (PieceType::O, 3) => (-1, 1),
+        (PieceType::I, 2) => (-1, 0),
+        (PieceType::I, 3) => (0, 1),
+        (PieceType::S, 0) => (0, 1),
+        (PieceType::S, 1) => (1, 0),
+        (PieceType::Z, 0) => (0, 1),
+        (PieceType::Z, 3) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+// Field position of a piece, or None if its centre is off the fumen field
+fn piece_position(record: &PlacementRecord) -> Option<u32> {
+    let (cx, cy) = Piece::srs_centre(record.piece, record.placement.rotation);
+    let (dx, dy) = stored_offset(record.piece, record.placement.rotation);
+    let x = record.placement.x + cx + dx;
+    // Fumen rows count up from the bottom row of the field
+    let y = (BOARD_HEIGHT as i32 - 1 - (record.placement.y + cy)) + dy;
+    let row = FIELD_TOP as i32 - 1 - y;
+    ((0..BOARD_WIDTH as i32).contains(&x) && (0..FIELD_ROWS as i32).contains(&row))
+        .then(|| (row * BOARD_WIDTH as i32 + x) as u32)
+}
+
+fn piece_from_position(piece: PieceType, rotation: usize, position: u32) -> PlacementRecord {
+    let (cx, cy) = Piece::srs_centre(piece, rotation);
+    let (dx, dy) = stored_offset(piece, rotation);
+    let x = (position % BOARD_WIDTH as u32) as i32 - dx;
+    let y = FIELD_TOP as i32 - 1 - (position / BOARD_WIDTH as u32) as i32 - dy;
+    let placement = Placement::new(x - cx, BOARD_HEIGHT as i32 - 1 - y - cy, rotation);
+    PlacementRecord { piece, placement, hold: false }
+}
+
+// Fumen's own field: piece codes, top row first, garbage row last
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Field([[u8; BOARD_WIDTH]; FIELD_ROWS]);
+
+impl Field {
+    fn empty() -> Self {
+        Field([[0; BOARD_WIDTH]; FIELD_ROWS])
+    }
+
+    // Rows above the fumen field are dropped
+    fn from_board(board: &Board) -> Self {
+        let mut field = Self::empty();
+        for (row, cells) in field.0[..FIELD_TOP].iter_mut().enumerate() {
+            for (x, cell) in cells.iter_mut().enumerate() {
+                if board.get_cell(x, ROW_OFFSET + row) {
+                    *cell = match board.color(x, ROW_OFFSET + row) {
+                        Some(CellColor::Piece(piece)) => piece_code(piece),
+                        _ => GRAY,
+                    };
+                }
+            }
+        }
+        field
+    }
+
+    fn to_board(self) -> Board {
+        let mut board = Board::new().with_colors();
+        for (row, cells) in self.0[..FIELD_TOP].iter().enumerate() {
+            for (x, &cell) in cells.iter().enumerate() {
+                if cell != 0 {
+                    board.set_cell(x, ROW_OFFSET + row, true);
+                    board.set_color(x, ROW_OFFSET + row, code_piece(cell).map_or(CellColor::Garbage, CellColor::Piece));
+                }
+            }
+        }
+        board
+    }
+
+    fn cell(&mut self, index: usize) -> &mut u8 {
+        &mut self.0[index / BOARD_WIDTH][index % BOARD_WIDTH]
+    }
+
+    // Locks `record` and clears full rows; the garbage row never clears
+    fn lock(&mut self, record: &PlacementRecord) {
+        let piece = Piece::new(record.piece, record.placement.x, record.placement.y).with_rotation(record.placement.rotation);
+        for (x, y) in piece.cells() {
+            let row = y - ROW_OFFSET as i32;
+            if (0..BOARD_WIDTH as i32).contains(&x) && (0..FIELD_TOP as i32).contains(&row) {
+                self.0[row as usize][x as usize] = piece_code(record.piece);
+            }
+        }
+        let kept: Vec<[u8; BOARD_WIDTH]> = self.0[..FIELD_TOP].iter().copied().filter(|row| row.contains(&0)).collect();
+        let cleared = FIELD_TOP - kept.len();
+        self.0[..cleared].fill([0; BOARD_WIDTH]);
+        self.0[cleared..FIELD_TOP].copy_from_slice(&kept);
+    }
+
+    // Pushes the garbage row up into the field
+    fn rise(&mut self) {
+        self.0.copy_within(1.., 0);
+        self.0[FIELD_TOP] = [0; BOARD_WIDTH];
+    }
+
+    fn mirror(&mut self) {
+        for row in &mut self.0[..FIELD_TOP] {
+            row.reverse();
+        }
+    }
+}
+
+// Fumen data is little-endian base 64
+struct Reader {
+    digits: Vec<u32>,
+    position: usize,
+}
+
+impl Reader {
+    fn poll(&mut self, count: usize) -> Result<u32, FumenError> {
+        let digits = self.digits.get(self.position..self.position + count).ok_or(FumenError::Truncated)?;
+        self.position += count;
+        Ok(digits.iter().rev().fold(0, |value, &digit| value * 64 + digit))
+    }
+
+    fn is_end(&self) -> bool {
+        self.position >= self.digits.len()
+    }
+}
+
+fn push(digits: &mut Vec<u32>, mut value: u32, count: usize) {
+    for _ in 0..count {
+        digits.push(value % 64);
+        value /= 64;
+    }
+}
+
+// JavaScript's `escape`, which fumen runs comments through
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if unit < 0x100 => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (unit, length) = match rest.strip_prefix("%u").and_then(|s| s.get(..4)).and_then(|s| u16::from_str_radix(s, 16).ok()) {
+            Some(unit) => (unit, 6),
+            None => match rest.strip_prefix('%').and_then(|s| s.get(..2)).and_then(|s| u16::from_str_radix(s, 16).ok()) {
+                Some(unit) => (unit, 3),
+                None => (c as u16, 1),
+            },
+        };
+        units.push(unit);
+        rest = &rest[length..];
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn read_comment(reader: &mut Reader) -> Result<String, FumenError> {
+    let length = reader.poll(2)? as usize;
+    let mut escaped = String::with_capacity(length);
+    for _ in 0..length.div_ceil(4) {
+        let mut value = reader.poll(5)?;
+        for _ in 0..4 {
+            escaped.push((b' ' + (value % COMMENT_BASE) as u8) as char);
+            value /= COMMENT_BASE;
+        }
+    }
+    escaped.truncate(length);
+    if escaped.contains('\u{7f}') {
+        return Err(FumenError::Corrupt);
+    }
+    Ok(unescape(&escaped))
+}
+
+fn write_comment(digits: &mut Vec<u32>, comment: &str) {
+    let mut escaped = escape(comment);
+    escaped.truncate(MAX_COMMENT);
+    push(digits, escaped.len() as u32, 2);
+    for chunk in escaped.as_bytes().chunks(4) {
+        let value = chunk.iter().rev().fold(0, |value, &c| value * COMMENT_BASE + (c - b' ') as u32);
+        push(digits, value, 5);
+    }
+}
+
+// Reads every page of a fumen; anything before the `v115@` marker, such as
+// the editor URL, is ignored
+pub fn decode(fumen: &str) -> Result<Vec<FumenPage>, FumenError> {
+    let start = ["v115@", "m115@", "d115@"].iter().find_map(|marker| fumen.find(marker)).ok_or(FumenError::Version)?;
+    let digits = fumen[start + 5..]
+        .trim()
+        .chars()
+        .filter(|&c| c != '?')
+        .map(|c| {
+            ENCODE_TABLE.iter().position(|&symbol| symbol as char == c).map(|digit| digit as u32).ok_or(FumenError::BadChar(c))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut reader = Reader { digits, position: 0 };
+
+    let mut pages = Vec::new();
+    let mut field = Field::empty();
+    let mut comment = String::new();
+    let mut repeat = 0;
+    while !reader.is_end() {
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            let mut changed = false;
+            while index < FIELD_CELLS {
+                let value = reader.poll(2)?;
+                let (diff, run) = (value / FIELD_CELLS, value % FIELD_CELLS + 1);
+                if index + run > FIELD_CELLS {
+                    return Err(FumenError::Corrupt);
+                }
+                changed |= diff != GRAY as u32;
+                for cell in index..index + run {
+                    let cell = field.cell(cell as usize);
+                    *cell = (*cell as u32 + diff).checked_sub(8).filter(|&code| code <= GRAY as u32).ok_or(FumenError::Corrupt)? as u8;
+                }
+                index += run;
+            }
+            if !changed {
+                repeat = reader.poll(1)?;
+            }
+        }
+
+        let action = reader.poll(3)?;
+        let rotation = [2, 1, 0, 3][(action / 8 % 4) as usize];
+        let position = action / 32 % FIELD_CELLS;
+        let flags = action / 32 / FIELD_CELLS;
+        let piece = match action % 8 {
+            0 => None,
+            code => Some(piece_from_position(code_piece(code as u8).ok_or(FumenError::Corrupt)?, rotation, position)),
+        };
+        if flags & 8 != 0 {
+            comment = read_comment(&mut reader)?;
+        }
+        let lock = flags & 16 == 0;
+
+        pages.push(FumenPage { board: field.to_board(), piece, comment: comment.clone(), lock });
+        if lock {
+            if let Some(piece) = &piece {
+                field.lock(piece);
+            }
+            if flags & 1 != 0 {
+                field.rise();
+            }
+            if flags & 2 != 0 {
+                field.mirror();
+            }
+        }
+    }
+    Ok(pages)
+}
+
+// Writes `pages` as a v115 fumen. Rows above the fumen field and pieces
+// centred outside it are left out.
+pub fn encode(pages: &[FumenPage]) -> String {
+    let mut digits = Vec::new();
+    let mut previous = Field::empty();
+    let mut previous_comment = "";
+    // Where the count of pages repeating the last field is kept
+    let mut repeat_slot: Option<usize> = None;
+    for (index, page) in pages.iter().enumerate() {
+        let mut field = Field::from_board(&page.board);
+        let (diff, changed) = field_diff(&previous, &field);
+        if changed {
+            digits.extend(diff);
+            repeat_slot = None;
+        } else {
+            match repeat_slot {
+                Some(slot) if digits[slot] < 63 => digits[slot] += 1,
+                _ => {
+                    digits.extend(diff);
+                    repeat_slot = Some(digits.len());
+                    digits.push(0);
+                }
+            }
+        }
+
+        let piece = page.piece.and_then(|piece| piece_position(&piece).map(|position| (piece, position)));
+        let (code, rotation, position) = match &piece {
+            Some((record, position)) => (piece_code(record.piece) as u32, rotation_code(record.placement.rotation), *position),
+            None => (0, 0, 0),
+        };
+        let comment_changed = page.comment != previous_comment;
+        // Only the first page turns on colors; the lock bit is inverted
+        let flags = if index == 0 { 4 } else { 0 } | if comment_changed { 8 } else { 0 } | if page.lock { 0 } else { 16 };
+        push(&mut digits, code + 8 * (rotation + 4 * (position + FIELD_CELLS * flags)), 3);
+        if comment_changed {
+            write_comment(&mut digits, &page.comment);
+        }
+
+        if page.lock {
+            if let Some((record, _)) = &piece {
+                field.lock(record);
+            }
+        }
+        previous = field;
+        previous_comment = &page.comment;
+    }
+    let data: String = digits.iter().map(|&digit| ENCODE_TABLE[digit as usize] as char).collect();
+    format!("v115@{}", data)
+}
+
+// Run-length diff from `previous` to `field`, and whether anything changed
+fn field_diff(previous: &Field, field: &Field) -> (Vec<u32>, bool) {
+    let diffs = (0..FIELD_ROWS * BOARD_WIDTH).map(|index| {
+        let (row, x) = (index / BOARD_WIDTH, index % BOARD_WIDTH);
+        (field.0[row][x] as u32 + 8) - previous.0[row][x] as u32
+    });
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for diff in diffs {
+        match runs.last_mut() {
+            Some((last, run)) if *last == diff => *run += 1,
+            _ => runs.push((diff, 1)),
+        }
+    }
+    let changed = runs.iter().any(|&(diff, _)| diff != GRAY as u32);
+    let mut digits = Vec::new();
+    for (diff, run) in runs {
+        push(&mut digits, diff * FIELD_CELLS + run - 1, 2);
+    }
+    (digits, changed)
+}
+
+// A page per placement, each locking on the field the last one left: the way
+// to share a line of play
+pub fn placement_pages(board: &Board, placements: &[PlacementRecord]) -> Vec<FumenPage> {
+    let mut field = Field::from_board(board);
+    placements
+        .iter()
+        .map(|record| {
+            let board = field.to_board();
+            field.lock(record);
+            FumenPage::new(board).with_piece(record.piece, record.placement)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::Strategy;
+    use crate::search::SearchEngine;
+    use crate::sim::Game;
+
+    #[test]
+    fn empty_page_matches_the_editor() {
+        assert_eq!(encode(&[FumenPage::new(Board::new())]), "v115@vhAAgH");
+        let pages = decode("https://fumen.zui.jp/?v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].board, Board::new());
+        assert_eq!(pages[0].piece, None);
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        let board: Board = "IIII......\nTTTSS..LLL\nGGGGGGGG.G".parse().unwrap();
+        let pages = [
+            FumenPage::new(board.clone()).with_piece(PieceType::T, Placement::new(5, 36, 2)).with_comment("#Q=[](T)SZ"),
+            // Same field again, so the repeat count is used
+            FumenPage::new(board.clone()).with_piece(PieceType::O, Placement::new(7, 36, 0)).with_lock(false),
+            FumenPage::new(board).with_comment("tuck — déjà vu ✓"),
+            FumenPage::new(Board::new()),
+        ];
+        let decoded = decode(&encode(&pages)).unwrap();
+        assert_eq!(decoded.len(), pages.len());
+        for (page, back) in pages.iter().zip(&decoded) {
+            assert_eq!(back.board, page.board);
+            assert_eq!(back.board.colors(), page.board.clone().with_colors().colors());
+            assert_eq!(back.piece, page.piece);
+            assert_eq!(back.comment, page.comment);
+            assert_eq!(back.lock, page.lock);
+        }
+        assert_eq!(decoded[0].queue().unwrap().next, vec![PieceType::S, PieceType::Z]);
+    }
+
+    #[test]
+    fn game_round_trips_as_placement_pages() {
+        let mut game = Game::new(5, 1);
+        game.play_bot(&mut SearchEngine::new(), Strategy::Balanced, 12);
+        let pages = decode(&encode(&placement_pages(&Board::new(), game.placements()))).unwrap();
+        let pieces: Vec<PlacementRecord> = pages.iter().filter_map(|page| page.piece).collect();
+        assert_eq!(pieces, game.placements());
+
+        let mut last = Field::from_board(&pages[11].board);
+        last.lock(&pieces[11]);
+        assert_eq!(last.to_board(), *game.board());
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        assert_eq!(decode("v114@vhAAgH").unwrap_err(), FumenError::Version);
+        assert_eq!(decode("v115@vh!AgH").unwrap_err(), FumenError::BadChar('!'));
+        assert_eq!(decode("v115@vhAAg").unwrap_err(), FumenError::Truncated);
+    }
+}
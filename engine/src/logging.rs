@@ -0,0 +1,67 @@
+use std::sync::RwLock;
+
+// Where `console_log!` output goes. The browser console only exists on wasm
+// builds with the `wasm` feature; anywhere else `Console` writes to stderr.
+#[derive(Clone, Copy, Debug)]
+pub enum LogSink {
+    Console,
+    Stderr,
+    // The `log` facade at info level, for hosts that already route it
+    #[cfg(feature = "log")]
+    Log,
+    Silent,
+    Custom(fn(&str)),
+}
+
+impl LogSink {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "console" => Some(LogSink::Console),
+            "stderr" => Some(LogSink::Stderr),
+            #[cfg(feature = "log")]
+            "log" => Some(LogSink::Log),
+            "silent" => Some(LogSink::Silent),
+            _ => None,
+        }
+    }
+}
+
+impl Default for LogSink {
+    fn default() -> Self {
+        if cfg!(all(target_arch = "wasm32", feature = "wasm")) { LogSink::Console } else { LogSink::Stderr }
+    }
+}
+
+static SINK: RwLock<Option<LogSink>> = RwLock::new(None);
+
+// Applies to the whole process, search threads included
+pub fn set_log_sink(sink: LogSink) {
+    *SINK.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(sink);
+}
+
+pub fn log_sink() -> LogSink {
+    SINK.read().unwrap_or_else(|poisoned| poisoned.into_inner()).unwrap_or_default()
+}
+
+pub fn write(message: &str) {
+    match log_sink() {
+        LogSink::Console => console(message),
+        LogSink::Stderr => eprintln!("{}", message),
+        #[cfg(feature = "log")]
+        LogSink::Log => log::info!("{}", message),
+        LogSink::Silent => {}
+        LogSink::Custom(sink) => sink(message),
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console, js_name = log)]
+    fn console(s: &str);
+}
+
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+fn console(message: &str) {
+    eprintln!("{}", message);
+}
@@ -0,0 +1,196 @@
+use crate::bag::{BagRule, BagState, PieceRng};
+use crate::board::Board;
+use crate::evaluation::EvaluationWeights;
+use crate::pieces::{PieceType, Placement};
+use crate::search::{PlacementEvaluation, SearchEngine, NO_PLACEMENT_SCORE};
+
+pub const DEFAULT_MCTS_ITERATIONS: usize = 400;
+// Placements kept per node, best static score first
+const MAX_CHILDREN: usize = 12;
+// Pieces played from the root before a rollout is scored
+const HORIZON: usize = 4;
+const EXPLORATION: f64 = 1.4;
+
+struct Edge {
+    placement: Placement,
+    eval: PlacementEvaluation,
+    visits: u32,
+    total: f64,
+    // Child decision node for each piece that followed this placement
+    outcomes: Vec<(PieceType, usize)>,
+}
+
+impl Edge {
+    fn mean(&self) -> f64 {
+        self.total / self.visits as f64
+    }
+}
+
+// A known piece waiting to be placed on `board`
+struct Node {
+    board: Board,
+    piece: PieceType,
+    queue: Vec<PieceType>,
+    bag: BagState,
+    depth: usize,
+    visits: u32,
+    edges: Option<Vec<Edge>>,
+}
+
+// Monte Carlo tree search where chance nodes are pieces drawn from the bag
+// model and leaves are scored by greedy rollouts with the static evaluator.
+pub struct MctsSearch<'a> {
+    engine: &'a SearchEngine,
+    weights: &'a EvaluationWeights,
+    bag_rule: BagRule,
+    rng: PieceRng,
+    nodes: Vec<Node>,
+}
+
+impl<'a> MctsSearch<'a> {
+    pub fn new(engine: &'a SearchEngine, weights: &'a EvaluationWeights, bag_rule: BagRule, seed: u64) -> Self {
+        Self {
+            engine,
+            weights,
+            bag_rule,
+            rng: PieceRng::new(seed),
+            nodes: Vec::new(),
+        }
+    }
+
+    // Returns the most visited root placement and its mean value
+    pub fn run(mut self, board: &Board, piece: PieceType, queue: &[PieceType], bag: BagState, iterations: usize) -> Option<(Placement, PlacementEvaluation, f64)> {
+        let root = self.add_node(board.without_colors(), piece, queue.to_vec(), bag, 0);
+        for _ in 0..iterations.max(1) {
+            self.iterate(root);
+        }
+
+        let edges = self.nodes[root].edges.take()?;
+        edges
+            .into_iter()
+            .filter(|edge| edge.visits > 0)
+            .reduce(|best, edge| if edge.visits > best.visits { edge } else { best })
+            .map(|edge| {
+                let value = edge.mean();
+                (edge.placement, edge.eval, value)
+            })
+    }
+
+    fn add_node(&mut self, board: Board, piece: PieceType, queue: Vec<PieceType>, bag: BagState, depth: usize) -> usize {
+        self.nodes.push(Node { board, piece, queue, bag, depth, visits: 0, edges: None });
+        self.nodes.len() - 1
+    }
+
+    fn iterate(&mut self, index: usize) -> f64 {
+        if self.nodes[index].edges.is_none() {
+            let node = &self.nodes[index];
+            let edges = self.engine.rank_placements(&node.board, node.piece, self.weights)
+                .into_iter()
+                .take(MAX_CHILDREN)
+                .map(|(placement, eval)| Edge { placement, eval, visits: 0, total: 0.0, outcomes: Vec::new() })
+                .collect();
+            self.nodes[index].edges = Some(edges);
+        }
+
+        let Some(choice) = self.select(index) else {
+            return NO_PLACEMENT_SCORE;
+        };
+
+        let node = &self.nodes[index];
+        let edge = &node.edges.as_ref().unwrap()[choice];
+        let board = edge.eval.predicted_board.clone();
+        let (score, reward) = (edge.eval.score, edge.eval.reward);
+        let first_visit = edge.visits == 0;
+        let depth = node.depth + 1;
+
+        // Known preview pieces come first, then the bag decides
+        let (piece, queue, bag) = match node.queue.split_first() {
+            Some((&piece, rest)) => (piece, rest.to_vec(), node.bag),
+            None => {
+                let bag = node.bag;
+                let piece = self.bag_rule.deal(&bag, &mut self.rng);
+                (piece, Vec::new(), bag.take(piece))
+            }
+        };
+
+        // Values count every reward along the line, this placement's included
+        let value = if depth >= HORIZON {
+            score
+        } else if first_visit {
+            reward + self.rollout(board, piece, &queue, bag, depth)
+        } else {
+            let existing = self.nodes[index].edges.as_ref().unwrap()[choice]
+                .outcomes
+                .iter()
+                .find(|(outcome, _)| *outcome == piece)
+                .map(|&(_, child)| child);
+            let child = match existing {
+                Some(child) => child,
+                None => {
+                    let child = self.add_node(board, piece, queue, bag, depth);
+                    self.nodes[index].edges.as_mut().unwrap()[choice].outcomes.push((piece, child));
+                    child
+                }
+            };
+            reward + self.iterate(child)
+        };
+
+        let node = &mut self.nodes[index];
+        node.visits += 1;
+        let edge = &mut node.edges.as_mut().unwrap()[choice];
+        edge.visits += 1;
+        edge.total += value;
+        value
+    }
+
+    // UCB1 over placements, with values rescaled to [0, 1] across siblings.
+    // Unvisited placements are tried first, best static score first.
+    fn select(&self, index: usize) -> Option<usize> {
+        let node = &self.nodes[index];
+        let edges = node.edges.as_ref()?;
+        if let Some(unvisited) = edges.iter().position(|edge| edge.visits == 0) {
+            return Some(unvisited);
+        }
+
+        let (low, high) = edges.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), edge| {
+            (low.min(edge.mean()), high.max(edge.mean()))
+        });
+        let range = high - low;
+        let log_visits = (node.visits.max(1) as f64).ln();
+
+        edges
+            .iter()
+            .enumerate()
+            .map(|(i, edge)| {
+                let exploit = if range > 0.0 { (edge.mean() - low) / range } else { 0.5 };
+                let explore = EXPLORATION * (log_visits / edge.visits as f64).sqrt();
+                (i, exploit + explore)
+            })
+            .reduce(|best, candidate| if candidate.1 > best.1 { candidate } else { best })
+            .map(|(i, _)| i)
+    }
+
+    // Play greedily by static score up to the horizon, then score the board
+    // plus every reward collected on the way
+    fn rollout(&mut self, mut board: Board, piece: PieceType, queue: &[PieceType], mut bag: BagState, depth: usize) -> f64 {
+        let mut piece = piece;
+        let mut queue = queue.iter();
+        let mut rewards = 0.0;
+        for _ in depth..HORIZON {
+            let Some((_, eval)) = self.engine.rank_placements(&board, piece, self.weights).into_iter().next() else {
+                return NO_PLACEMENT_SCORE;
+            };
+            rewards += eval.reward;
+            board = eval.predicted_board;
+            piece = match queue.next() {
+                Some(&next) => next,
+                None => {
+                    let next = self.bag_rule.deal(&bag, &mut self.rng);
+                    bag = bag.take(next);
+                    next
+                }
+            };
+        }
+        rewards + board.evaluate(self.weights).score
+    }
+}
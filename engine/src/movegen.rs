@@ -0,0 +1,439 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use serde::{Deserialize, Serialize};
+use crate::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::evaluation::SpinKind;
+use crate::pieces::{Piece, PieceType, Placement, PIECE_SHAPES};
+
+// Leftmost piece x; bounding boxes may hang off the left wall
+const MIN_X: i32 = -3;
+// Piece x positions MIN_X..MIN_X + 16 as bits of a u16
+const X_SLOTS: usize = 16;
+const ROTATIONS: usize = 4;
+const STATES: usize = ROTATIONS * BOARD_HEIGHT * X_SLOTS;
+// Everything but the playfield, with column c at bit c - MIN_X
+const WALLS: u32 = !(((1 << BOARD_WIDTH) - 1) << -MIN_X);
+
+// One player input short of locking, named as in move sequences
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Input {
+    RotateCw,
+    RotateCcw,
+    Left,
+    Right,
+    DasLeft,
+    DasRight,
+    SoftDrop,
+    SonicDrop,
+}
+
+impl Input {
+    pub const ALL: [Input; 8] = [
+        Input::RotateCw, Input::RotateCcw, Input::Left, Input::Right,
+        Input::DasLeft, Input::DasRight, Input::SoftDrop, Input::SonicDrop,
+    ];
+
+    pub fn from_action(action: &str) -> Option<Self> {
+        Input::ALL.into_iter().find(|input| input.action() == action)
+    }
+
+    pub fn action(self) -> &'static str {
+        match self {
+            Input::RotateCw => "rotate",
+            Input::RotateCcw => "rotate_ccw",
+            Input::Left => "move_left",
+            Input::Right => "move_right",
+            Input::DasLeft => "move_all_the_way_left",
+            Input::DasRight => "move_all_the_way_right",
+            Input::SoftDrop => "move_down",
+            Input::SonicDrop => "move_to_bottom",
+        }
+    }
+
+    // Where `piece` ends up on `board`, or None if the input is blocked and
+    // leaves it where it was. Rotation has no kicks.
+    pub fn apply(self, board: &Board, piece: &Piece) -> Option<Piece> {
+        let slide = |dx: i32, dy: i32| {
+            let mut piece = *piece;
+            while board.can_place_piece(&piece.moved(dx, dy)) {
+                piece = piece.moved(dx, dy);
+            }
+            piece
+        };
+        let moved = match self {
+            Input::RotateCw => piece.rotated(true),
+            Input::RotateCcw => piece.rotated(false),
+            Input::Left => piece.moved(-1, 0),
+            Input::Right => piece.moved(1, 0),
+            Input::DasLeft => slide(-1, 0),
+            Input::DasRight => slide(1, 0),
+            Input::SoftDrop => piece.moved(0, 1),
+            Input::SonicDrop => slide(0, 1),
+        };
+        (moved != *piece && board.can_place_piece(&moved)).then_some(moved)
+    }
+}
+
+// Spin credit at `placement` from the spot alone, without a flood fill: the
+// piece counts as rotated in if it fits there in a neighbouring rotation.
+// Agrees with `MoveMap::spin` whenever that rotation is reachable.
+pub fn spin_at(board: &Board, piece_type: PieceType, placement: &Placement) -> SpinKind {
+    let piece = Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation);
+    if placement.rotation >= ROTATIONS || piece_type == PieceType::O || !board.can_place_piece(&piece) {
+        return SpinKind::None;
+    }
+    let rotated_in = [true, false].into_iter().any(|clockwise| board.can_place_piece(&piece.rotated(clockwise)));
+    spin_kind(board, piece_type, placement, rotated_in, |dx, dy| board.can_place_piece(&piece.moved(dx, dy)))
+}
+
+// Spin credit for a piece at `placement` that did or did not rotate in last.
+// `fits(dx, dy)` says whether it could move by that much.
+fn spin_kind(board: &Board, piece_type: PieceType, placement: &Placement, rotated_in: bool, fits: impl Fn(i32, i32) -> bool) -> SpinKind {
+    if !rotated_in {
+        return SpinKind::None;
+    }
+
+    if piece_type != PieceType::T {
+        let stuck = !fits(-1, 0) && !fits(1, 0) && !fits(0, -1);
+        return if stuck { SpinKind::Mini } else { SpinKind::None };
+    }
+
+    // Corners of the T's 3x3 box clockwise from the top left; walls count
+    let rotation = placement.rotation;
+    let filled = [(0, 0), (2, 0), (2, 2), (0, 2)].map(|(dx, dy)| {
+        let (x, y) = (placement.x + dx, placement.y + dy);
+        x < 0 || x >= BOARD_WIDTH as i32 || y >= BOARD_HEIGHT as i32 || board.get_cell(x as usize, y as usize)
+    });
+    // The T points between corners `rotation` and `rotation + 1`
+    let front = filled[rotation] && filled[(rotation + 1) % ROTATIONS];
+    match filled.iter().filter(|&&corner| corner).count() {
+        0..=2 => SpinKind::None,
+        _ if front => SpinKind::Full,
+        _ => SpinKind::Mini,
+    }
+}
+
+// Plays a move sequence from spawn and returns where the piece locks. Blocked
+// inputs do nothing. None if the piece cannot spawn, an action is not a
+// movement, or the sequence does not end with its only hard drop.
+pub fn play_inputs(board: &Board, piece_type: PieceType, actions: &[String]) -> Option<Piece> {
+    trace_inputs(board, piece_type, actions).map(|(piece, _)| piece)
+}
+
+// `play_inputs`, also telling whether the last input that moved the piece
+// was a rotation, so the lock may score a spin
+pub fn trace_inputs(board: &Board, piece_type: PieceType, actions: &[String]) -> Option<(Piece, bool)> {
+    let (hard_drop, movement) = actions.split_last()?;
+    let mut piece = Piece::spawn(piece_type);
+    if hard_drop != "hard_drop" || !board.can_place_piece(&piece) {
+        return None;
+    }
+    let mut rotated = false;
+    for action in movement {
+        let input = Input::from_action(action)?;
+        if let Some(moved) = input.apply(board, &piece) {
+            piece = moved;
+            rotated = matches!(input, Input::RotateCw | Input::RotateCcw);
+        }
+    }
+    Some((Input::SonicDrop.apply(board, &piece).unwrap_or(piece), rotated))
+}
+
+// Every position a piece can reach from spawn on one board, as bitboards:
+// row y of rotation r holds one bit per piece x. Whole rows are shifted and
+// masked at once, so the flood fill touches each (rotation, row) only a few
+// times instead of testing positions one by one. Rotation has no kicks.
+pub struct MoveMap {
+    board: Board,
+    piece_type: PieceType,
+    // Bit x - MIN_X is set where the piece fits at (x, y) in rotation r
+    fits: [[u16; BOARD_HEIGHT]; ROTATIONS],
+    reach: [[u16; BOARD_HEIGHT]; ROTATIONS],
+}
+
+impl MoveMap {
+    pub fn new(board: &Board, piece_type: PieceType) -> Self {
+        let mut fits = [[0u16; BOARD_HEIGHT]; ROTATIONS];
+        for (rotation, rows) in fits.iter_mut().enumerate() {
+            let shape = PIECE_SHAPES[piece_type as usize][rotation];
+            for (y, fit) in rows.iter_mut().enumerate() {
+                // A cell at offset dx blocks piece x wherever column x + dx is taken
+                let mut blocked = 0u32;
+                for (dy, &shape_row) in shape.iter().enumerate() {
+                    let row = board.rows.get(y + dy).map_or(u32::MAX, |&row| (row << -MIN_X) | WALLS);
+                    let mut cells = shape_row;
+                    while cells != 0 {
+                        blocked |= row >> cells.trailing_zeros();
+                        cells &= cells - 1;
+                    }
+                }
+                *fit = !blocked as u16;
+            }
+        }
+
+        let mut map = Self { board: board.without_colors(), piece_type, fits, reach: [[0; BOARD_HEIGHT]; ROTATIONS] };
+        map.flood();
+        map
+    }
+
+    // Nothing moves a piece up, so one pass from the spawn row down is enough:
+    // close each row under shifts and rotations, then let it fall a row.
+    fn flood(&mut self) {
+        let spawn = Piece::spawn(self.piece_type);
+        let spawn_y = spawn.y as usize;
+        self.reach[0][spawn_y] = self.fits[0][spawn_y] & (1 << (spawn.x - MIN_X));
+
+        for y in spawn_y..BOARD_HEIGHT {
+            loop {
+                let before = self.reach.map(|rows| rows[y]);
+                for rotation in 0..ROTATIONS {
+                    let fits = self.fits[rotation][y];
+                    let mut row = self.reach[rotation][y];
+                    loop {
+                        let grown = (row | row << 1 | row >> 1) & fits;
+                        if grown == row {
+                            break;
+                        }
+                        row = grown;
+                    }
+                    self.reach[rotation][y] = row;
+                    for turned in [(rotation + 1) % ROTATIONS, (rotation + 3) % ROTATIONS] {
+                        self.reach[turned][y] |= row & self.fits[turned][y];
+                    }
+                }
+                if self.reach.map(|rows| rows[y]) == before {
+                    break;
+                }
+            }
+
+            if y + 1 < BOARD_HEIGHT {
+                for rotation in 0..ROTATIONS {
+                    self.reach[rotation][y + 1] |= self.reach[rotation][y] & self.fits[rotation][y + 1];
+                }
+            }
+        }
+    }
+
+    fn fits_at(&self, rotation: usize, y: usize, slot: usize) -> bool {
+        y < BOARD_HEIGHT && slot < X_SLOTS && self.fits[rotation][y] & (1 << slot) != 0
+    }
+
+    // Row the piece lands on when dropped from `y`
+    fn drop_y(&self, rotation: usize, mut y: usize, slot: usize) -> usize {
+        while self.fits_at(rotation, y + 1, slot) {
+            y += 1;
+        }
+        y
+    }
+
+    // Every reachable resting position, soft-drop tucks and spins included
+    pub fn placements(&self) -> Vec<Placement> {
+        let mut placements = Vec::new();
+        for rotation in 0..ROTATIONS {
+            for y in 0..BOARD_HEIGHT {
+                let below = self.fits[rotation].get(y + 1).copied().unwrap_or(0);
+                let mut landed = self.reach[rotation][y] & !below;
+                while landed != 0 {
+                    let slot = landed.trailing_zeros() as i32;
+                    placements.push(Placement::new(slot + MIN_X, y as i32, rotation));
+                    landed &= landed - 1;
+                }
+            }
+        }
+        placements
+    }
+
+    fn reached(&self, rotation: usize, y: usize, slot: usize) -> bool {
+        self.reach[rotation][y] & (1 << slot) != 0
+    }
+
+    // Spin credit for locking at `placement` straight after rotating into it
+    pub fn spin(&self, placement: &Placement) -> SpinKind {
+        let (Ok(y), Ok(slot)) = (usize::try_from(placement.y), usize::try_from(placement.x - MIN_X)) else {
+            return SpinKind::None;
+        };
+        let rotation = placement.rotation;
+        if rotation >= ROTATIONS || y >= BOARD_HEIGHT || slot >= X_SLOTS || self.piece_type == PieceType::O {
+            return SpinKind::None;
+        }
+        let rotated_in = [(rotation + 1) % ROTATIONS, (rotation + 3) % ROTATIONS]
+            .into_iter()
+            .any(|from| self.reached(from, y, slot));
+        let fits = |dx: i32, dy: i32| match (usize::try_from(y as i32 + dy), usize::try_from(slot as i32 + dx)) {
+            (Ok(y), Ok(slot)) => self.fits_at(rotation, y, slot),
+            _ => false,
+        };
+        spin_kind(&self.board, self.piece_type, placement, rotated_in, fits)
+    }
+
+    // Cheapest inputs from spawn to `placement`, ending in a hard drop. With
+    // ARR 0 a DAS charge to the wall costs `das`; every other input costs 1.
+    // Spin placements are finished with a rotation so the game credits the
+    // spin. Only parent pointers are kept; the path is rebuilt once at the end.
+    pub fn path(&self, placement: &Placement, arr: u32, das: u32) -> Option<Vec<String>> {
+        let target = (placement.rotation, usize::try_from(placement.y).ok()?, usize::try_from(placement.x - MIN_X).ok()?);
+        let index = |(rotation, y, slot): (usize, usize, usize)| (rotation * BOARD_HEIGHT + y) * X_SLOTS + slot;
+        let state = |index: usize| (index / (BOARD_HEIGHT * X_SLOTS), index / X_SLOTS % BOARD_HEIGHT, index % X_SLOTS);
+        let spin = self.spin(placement) != SpinKind::None;
+
+        let spawn = Piece::spawn(self.piece_type);
+        let start = (0, spawn.y as usize, (spawn.x - MIN_X) as usize);
+        if !self.fits_at(start.0, start.1, start.2) {
+            return None;
+        }
+
+        // (cost, inputs) to reach each state, and how it was reached
+        let mut best = vec![(usize::MAX, usize::MAX); STATES];
+        let mut parent: Vec<Option<(usize, Input)>> = vec![None; STATES];
+        let mut open = BinaryHeap::new();
+        best[index(start)] = (0, 0);
+        open.push(Reverse((0, 0, index(start))));
+        // Cheapest finishing rotation into a spin placement: (cost, inputs, from, input)
+        let mut spin_finish: Option<(usize, usize, usize, Input)> = None;
+
+        while let Some(Reverse((cost, inputs, current))) = open.pop() {
+            if spin_finish.is_some_and(|(finish_cost, finish_inputs, ..)| (cost, inputs) >= (finish_cost, finish_inputs)) {
+                break;
+            }
+            if (cost, inputs) > best[current] {
+                continue;
+            }
+            let (rotation, y, slot) = state(current);
+            if !spin && (rotation, self.drop_y(rotation, y, slot), slot) == target {
+                return Some(rebuild_path(&parent, current, None));
+            }
+
+            let mut next = Vec::with_capacity(8);
+            for (input, turned) in [(Input::RotateCw, (rotation + 1) % ROTATIONS), (Input::RotateCcw, (rotation + 3) % ROTATIONS)] {
+                if self.fits_at(turned, y, slot) {
+                    next.push((input, (turned, y, slot), 1));
+                }
+            }
+            if slot > 0 && self.fits_at(rotation, y, slot - 1) {
+                next.push((Input::Left, (rotation, y, slot - 1), 1));
+            }
+            if self.fits_at(rotation, y, slot + 1) {
+                next.push((Input::Right, (rotation, y, slot + 1), 1));
+            }
+            if arr == 0 {
+                let mut left = slot;
+                while left > 0 && self.fits_at(rotation, y, left - 1) {
+                    left -= 1;
+                }
+                if left != slot {
+                    next.push((Input::DasLeft, (rotation, y, left), das as usize));
+                }
+                let mut right = slot;
+                while self.fits_at(rotation, y, right + 1) {
+                    right += 1;
+                }
+                if right != slot {
+                    next.push((Input::DasRight, (rotation, y, right), das as usize));
+                }
+            }
+            let floor = self.drop_y(rotation, y, slot);
+            if floor > y {
+                next.push((Input::SonicDrop, (rotation, floor, slot), 1));
+            }
+            if floor > y + 1 {
+                next.push((Input::SoftDrop, (rotation, y + 1, slot), 1));
+            }
+
+            for (input, to, step_cost) in next {
+                let candidate = (cost + step_cost, inputs + 1);
+                let rotates = matches!(input, Input::RotateCw | Input::RotateCcw);
+                if spin && rotates && to == target
+                    && spin_finish.is_none_or(|(finish_cost, finish_inputs, ..)| candidate < (finish_cost, finish_inputs))
+                {
+                    spin_finish = Some((candidate.0, candidate.1, current, input));
+                }
+
+                let to = index(to);
+                if candidate < best[to] {
+                    best[to] = candidate;
+                    parent[to] = Some((current, input));
+                    open.push(Reverse((candidate.0, candidate.1, to)));
+                }
+            }
+        }
+        spin_finish.map(|(_, _, from, input)| rebuild_path(&parent, from, Some(input)))
+    }
+}
+
+// Walk parent pointers back from `at`, then add `last` and the hard drop
+fn rebuild_path(parent: &[Option<(usize, Input)>], mut at: usize, last: Option<Input>) -> Vec<String> {
+    let mut moves: Vec<String> = last.map(|input| input.action().to_string()).into_iter().collect();
+    while let Some((from, input)) = parent[at] {
+        moves.push(input.action().to_string());
+        at = from;
+    }
+    moves.reverse();
+    moves.push("hard_drop".to_string());
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+    use super::*;
+
+    const PIECES: [PieceType; 7] = [PieceType::I, PieceType::O, PieceType::T, PieceType::S, PieceType::Z, PieceType::J, PieceType::L];
+
+    fn boards() -> Vec<Board> {
+        // Empty, a T-spin double slot, and a stack with overhangs to tuck under
+        ["", "#..#######\n#...######\n##.#######", "##....#...\n#.....##..\n#..#...#..\n##.##.###."]
+            .into_iter()
+            .map(|grid| grid.parse().unwrap())
+            .collect()
+    }
+
+    // Resting spots found one input at a time from spawn
+    fn brute_force(board: &Board, piece_type: PieceType) -> HashSet<Placement> {
+        let spawn = Piece::spawn(piece_type);
+        let mut seen = HashSet::from([spawn]);
+        let mut open = VecDeque::from([spawn]);
+        let mut resting = HashSet::new();
+        while let Some(piece) = open.pop_front() {
+            if !board.can_place_piece(&piece.moved(0, 1)) {
+                resting.insert(Placement::new(piece.x, piece.y, piece.rotation));
+            }
+            for next in Input::ALL.iter().filter_map(|input| input.apply(board, &piece)) {
+                if seen.insert(next) {
+                    open.push_back(next);
+                }
+            }
+        }
+        resting
+    }
+
+    #[test]
+    fn finds_every_reachable_placement() {
+        for board in boards() {
+            for piece_type in PIECES {
+                let placements: HashSet<_> = MoveMap::new(&board, piece_type).placements().into_iter().collect();
+                assert_eq!(placements, brute_force(&board, piece_type), "{:?}", piece_type);
+            }
+        }
+    }
+
+    #[test]
+    fn paths_lock_where_planned() {
+        for board in boards() {
+            for piece_type in PIECES {
+                let moves = MoveMap::new(&board, piece_type);
+                for placement in moves.placements() {
+                    let path = moves.path(&placement, 0, 0).unwrap();
+                    let (piece, rotated) = trace_inputs(&board, piece_type, &path).unwrap();
+                    assert_eq!(Placement::new(piece.x, piece.y, piece.rotation), placement, "{:?} via {:?}", piece_type, path);
+                    if moves.spin(&placement) != SpinKind::None {
+                        assert!(rotated, "{:?} via {:?} loses the spin", piece_type, path);
+                    }
+                }
+            }
+        }
+        // The T-spin double is only reached by rotating in
+        let board = &boards()[1];
+        let tsd = Placement::new(1, BOARD_HEIGHT as i32 - 3, 2);
+        assert_eq!(MoveMap::new(board, PieceType::T).spin(&tsd), SpinKind::Full);
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::board::{Board, CellColor, BOARD_HEIGHT, BOARD_WIDTH, VISIBLE_HEIGHT};
+use crate::pieces::PieceType;
+use crate::render::RenderOptions;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoardParseError {
+    // More rows than the board has, hidden rows included
+    TooManyRows(usize),
+    // `line` counts from 1, blank lines included
+    RowWidth { line: usize, width: usize },
+    BadCell { line: usize, column: usize, cell: char },
+}
+
+impl fmt::Display for BoardParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardParseError::TooManyRows(rows) => write!(f, "{} rows given; the board has {}", rows, BOARD_HEIGHT),
+            BoardParseError::RowWidth { line, width } => write!(f, "line {}: {} cells, expected {}", line, width, BOARD_WIDTH),
+            BoardParseError::BadCell { line, column, cell } => write!(f, "line {}, column {}: unexpected {:?}", line, column + 1, cell),
+        }
+    }
+}
+
+impl std::error::Error for BoardParseError {}
+
+pub(crate) fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'I' => Some(PieceType::I),
+        'O' => Some(PieceType::O),
+        'T' => Some(PieceType::T),
+        'S' => Some(PieceType::S),
+        'Z' => Some(PieceType::Z),
+        'J' => Some(PieceType::J),
+        'L' => Some(PieceType::L),
+        _ => None,
+    }
+}
+
+fn cell_color(cell: char) -> Option<CellColor> {
+    match cell {
+        'G' => Some(CellColor::Garbage),
+        _ => piece_from_letter(cell).map(CellColor::Piece),
+    }
+}
+
+// "Row  3: ··········" as `display_board` logs it
+fn strip_row_label(line: &str) -> &str {
+    let labelled = line.strip_prefix("Row").and_then(|rest| rest.split_once(':'));
+    match labelled {
+        Some((number, cells)) if number.trim().parse::<usize>().is_ok() => cells.trim(),
+        _ => line,
+    }
+}
+
+// Reads a grid of 10-cell rows, top first, in either charset `render` writes.
+// Rows fill the board from the bottom, so more than 20 reach into the hidden
+// rows. '#', 'X', '█' and '▓' are filled, piece letters fill with their
+// piece and 'G' with garbage; '.', '·' and ghost cells ('+', '░') are empty.
+// Blank lines and surrounding spaces are ignored, and so are the title line
+// and `Row NN:` labels of `display_board` output. The board tracks colors if
+// any cell is a piece letter or 'G'.
+pub fn parse_board(text: &str) -> Result<Board, BoardParseError> {
+    let rows: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, strip_row_label(line.trim())))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('📋'))
+        .collect();
+    if rows.len() > BOARD_HEIGHT {
+        return Err(BoardParseError::TooManyRows(rows.len()));
+    }
+
+    let mut board = Board::new();
+    if rows.iter().any(|(_, cells)| cells.chars().any(|cell| cell_color(cell).is_some())) {
+        board = board.with_colors();
+    }
+    let top = BOARD_HEIGHT - rows.len();
+    for (row, &(line, cells)) in rows.iter().enumerate() {
+        let width = cells.chars().count();
+        if width != BOARD_WIDTH {
+            return Err(BoardParseError::RowWidth { line, width });
+        }
+        for (x, cell) in cells.chars().enumerate() {
+            let color = cell_color(cell);
+            let filled = match cell {
+                '#' | 'X' | '█' | '▓' => true,
+                '.' | '·' | '+' | '░' => false,
+                _ if color.is_some() => true,
+                _ => return Err(BoardParseError::BadCell { line, column: x, cell }),
+            };
+            board.set_cell(x, top + row, filled);
+            board.set_color(x, top + row, color.unwrap_or_default());
+        }
+    }
+    Ok(board)
+}
+
+impl FromStr for Board {
+    type Err = BoardParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_board(text)
+    }
+}
+
+// Serialized form of a board: every row, hidden ones included, top first, in
+// the ASCII grid `parse_board` reads, so colors survive as letters. `colors`
+// keeps the color layer on a board whose cells are all unknown.
+#[derive(Serialize, Deserialize)]
+struct BoardGrid {
+    rows: Vec<String>,
+    #[serde(default)]
+    colors: bool,
+}
+
+impl Serialize for Board {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let grid = self.render(&RenderOptions::default().with_hidden_rows(BOARD_HEIGHT - VISIBLE_HEIGHT));
+        BoardGrid { rows: grid.lines().map(str::to_string).collect(), colors: self.colors().is_some() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let grid = BoardGrid::deserialize(deserializer)?;
+        let board = parse_board(&grid.rows.join("\n")).map_err(D::Error::custom)?;
+        Ok(if grid.colors { board.with_colors() } else { board.without_colors() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Charset;
+
+    fn colored() -> Board {
+        "..T.....#.\n.TTT...ZZ.\nIIIIOO.SZZ\nGGGG.GGOSS\n".parse().unwrap()
+    }
+
+    #[test]
+    fn rows_fill_from_the_bottom() {
+        let board: Board = "#.........\n##.......X".parse().unwrap();
+        assert!(board.get_cell(0, BOARD_HEIGHT - 2));
+        assert!(board.get_cell(9, BOARD_HEIGHT - 1));
+        assert!(!board.get_cell(9, BOARD_HEIGHT - 2));
+        assert_eq!(board.colors(), None);
+
+        let board = colored();
+        assert_eq!(board.color(2, BOARD_HEIGHT - 4), Some(CellColor::Piece(PieceType::T)));
+        assert_eq!(board.color(0, BOARD_HEIGHT - 1), Some(CellColor::Garbage));
+        assert_eq!(board.color(8, BOARD_HEIGHT - 4), Some(CellColor::Unknown));
+        assert_eq!(board.color(4, BOARD_HEIGHT - 1), None);
+    }
+
+    #[test]
+    fn reads_what_render_writes() {
+        let board = colored();
+        let ascii: Board = board.render(&RenderOptions::default()).parse().unwrap();
+        assert_eq!(ascii, board);
+        assert_eq!(ascii.colors(), board.colors());
+
+        let unicode: Board = board.render(&RenderOptions::default().with_charset(Charset::Unicode)).parse().unwrap();
+        assert_eq!(unicode, board);
+
+        // As `display_board` logs it
+        let logged: String = board.render(&RenderOptions::default().with_charset(Charset::Unicode))
+            .lines()
+            .enumerate()
+            .map(|(row, line)| format!("Row {:2}: {}\n", row, line))
+            .collect();
+        assert_eq!(parse_board(&format!("📋 BOARD\n{}", logged)), Ok(board));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let mut board = colored();
+        board.set_cell(4, 0, true); // Top hidden row
+        let json = serde_json::to_string(&board).unwrap();
+        let back: Board = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, board);
+        assert_eq!(back.colors(), board.colors());
+
+        for board in [Board::new(), Board::new().with_colors()] {
+            let back: Board = serde_json::from_str(&serde_json::to_string(&board).unwrap()).unwrap();
+            assert_eq!(back.colors(), board.colors());
+        }
+    }
+
+    #[test]
+    fn reports_bad_grids() {
+        assert_eq!(parse_board("#########"), Err(BoardParseError::RowWidth { line: 1, width: 9 }));
+        assert_eq!(parse_board("\n....?....."), Err(BoardParseError::BadCell { line: 2, column: 4, cell: '?' }));
+        assert_eq!(parse_board(&"..........\n".repeat(BOARD_HEIGHT + 1)), Err(BoardParseError::TooManyRows(BOARD_HEIGHT + 1)));
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use crate::bag::{BagRule, BagState};
+use crate::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::pieces::{Piece, PieceType, Placement};
+use crate::search::{parallel_map, SearchEngine};
+
+pub const DEFAULT_PC_HEIGHT: usize = 4;
+pub const MAX_PC_HEIGHT: usize = 6;
+
+// One placement of a perfect clear sequence. `hold` is set when the piece was
+// reached by swapping with the hold slot instead of playing the active piece.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct PcStep {
+    pub piece: PieceType,
+    pub placement: Placement,
+    pub hold: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PcSolution {
+    pub height: usize,
+    pub steps: Vec<PcStep>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PcExample {
+    pub queue: Vec<PieceType>,
+    pub solution: PcSolution,
+}
+
+// Outcome of a "percent" run: how many of the possible piece orders clear
+#[derive(Clone, Debug, Serialize)]
+pub struct PcProbability {
+    pub successes: usize,
+    pub total: usize,
+    pub example: Option<PcExample>,
+}
+
+impl PcProbability {
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 { 0.0 } else { self.successes as f64 / self.total as f64 }
+    }
+}
+
+// Depth-first perfect clear finder. Placements come from the same generator the
+// bot uses, so every step of a solution is reachable under its movement rules.
+#[derive(Clone, Debug)]
+pub struct PerfectClearSolver {
+    pub max_height: usize,
+    pub use_hold: bool,
+}
+
+// Search position: index of the active piece in the sequence plus the hold slot.
+#[derive(Clone)]
+struct PcNode {
+    board: Board,
+    lines_left: usize,
+    index: usize,
+    hold: Option<PieceType>,
+}
+
+impl Default for PerfectClearSolver {
+    fn default() -> Self {
+        Self::new(DEFAULT_PC_HEIGHT)
+    }
+}
+
+impl PerfectClearSolver {
+    pub fn new(max_height: usize) -> Self {
+        Self {
+            max_height: max_height.clamp(1, MAX_PC_HEIGHT),
+            use_hold: true,
+        }
+    }
+
+    pub fn with_hold(mut self, use_hold: bool) -> Self {
+        self.use_hold = use_hold;
+        self
+    }
+
+    pub fn solve(&self, board: &Board, current: PieceType, hold: Option<PieceType>, queue: &[PieceType]) -> Option<PcSolution> {
+        let mut pieces = Vec::with_capacity(queue.len() + 1);
+        pieces.push(current);
+        pieces.extend_from_slice(queue);
+        let available = pieces.len() + usize::from(self.use_hold && hold.is_some());
+
+        for (height, needed) in self.heights(board) {
+            if needed > available {
+                continue;
+            }
+
+            let engine = SearchEngine::new();
+            let mut failed = HashSet::new();
+            let mut steps = Vec::new();
+            let root = PcNode {
+                board: board.without_colors(),
+                lines_left: height,
+                index: 0,
+                hold: if self.use_hold { hold } else { None },
+            };
+            if self.search(&engine, &root, &pieces, &mut steps, &mut failed) {
+                return Some(PcSolution { height, steps });
+            }
+        }
+        None
+    }
+
+    // Heights up to `max_height` a PC can end at from this board, lowest first,
+    // with the pieces each uses. The empty cells below must be an exact number
+    // of tetrominoes, which fixes the parity of the height.
+    pub fn heights(&self, board: &Board) -> Vec<(usize, usize)> {
+        let filled = filled_cells(board);
+        (stack_height(board).max(1)..=self.max_height)
+            .map(|height| (height, height * BOARD_WIDTH - filled))
+            .filter(|(_, empty)| empty.is_multiple_of(4))
+            .map(|(height, empty)| (height, empty / 4))
+            .collect()
+    }
+
+    // Pieces the PC uses at the lowest height reachable from this board
+    pub fn pieces_needed(&self, board: &Board) -> Option<usize> {
+        self.heights(board).first().map(|&(_, needed)| needed)
+    }
+
+    // `known` starts with the current piece; `bag` is what the randomizer still
+    // owes after the last known piece. Every unseen order is solved separately.
+    // Orders run as long as the tallest PC needs, and one counts if a PC at
+    // any height clears with it, as `solve` tries them lowest first.
+    pub fn probability(&self, board: &Board, hold: Option<PieceType>, known: &[PieceType], rule: BagRule, bag: BagState) -> PcProbability {
+        let Some(&(_, needed)) = self.heights(board).last() else {
+            return PcProbability { successes: 0, total: 0, example: None };
+        };
+
+        // Holding into an empty slot needs one extra piece of preview
+        let length = needed + usize::from(self.use_hold && hold.is_none());
+        let unseen = length.saturating_sub(known.len());
+        let queues: Vec<Vec<PieceType>> = rule.sequences(bag, unseen)
+            .into_iter()
+            .map(|tail| known.iter().copied().chain(tail).collect())
+            .collect();
+
+        let solve = |queue: &Vec<PieceType>| match queue.split_first() {
+            Some((&current, rest)) => self.solve(board, current, hold, rest),
+            None => None,
+        };
+
+        let solutions = parallel_map(true, &queues, solve);
+
+        let total = solutions.len();
+        let successes = solutions.iter().filter(|solution| solution.is_some()).count();
+        let example = queues.into_iter()
+            .zip(solutions)
+            .find_map(|(queue, solution)| solution.map(|solution| PcExample { queue, solution }));
+
+        PcProbability {
+            successes,
+            total,
+            example,
+        }
+    }
+
+    fn search(&self, engine: &SearchEngine, node: &PcNode, pieces: &[PieceType], steps: &mut Vec<PcStep>, failed: &mut HashSet<(u64, usize, usize, Option<PieceType>)>) -> bool {
+        if node.lines_left == 0 {
+            return true;
+        }
+
+        let remaining = pieces.len() - node.index + usize::from(node.hold.is_some());
+        let empty = node.lines_left * BOARD_WIDTH - filled_cells(&node.board);
+        if empty > remaining * 4 {
+            return false;
+        }
+
+        let key = (region_key(&node.board, node.lines_left), node.lines_left, node.index, node.hold);
+        if failed.contains(&key) {
+            return false;
+        }
+
+        // (piece to place, next index, next hold, placed from hold)
+        let mut options = Vec::with_capacity(2);
+        if let Some(&active) = pieces.get(node.index) {
+            options.push((active, node.index + 1, node.hold, false));
+            if self.use_hold {
+                match node.hold {
+                    Some(held) if held != active => options.push((held, node.index + 1, Some(active), true)),
+                    None => {
+                        if let Some(&next) = pieces.get(node.index + 1) {
+                            options.push((next, node.index + 2, Some(active), true));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (piece_type, next_index, next_hold, from_hold) in options {
+            let mut seen = HashSet::new();
+            for placement in engine.generate_all_placements(&node.board, piece_type) {
+                let piece = Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation);
+                if !fits_below(&piece, node.lines_left) {
+                    continue;
+                }
+
+                let mut next_board = node.board.clone();
+                next_board.lock_piece(&piece);
+                if !seen.insert(next_board.rows) {
+                    continue; // Same cells as a placement we already tried
+                }
+                let clear_info = next_board.clear_lines();
+
+                steps.push(PcStep { piece: piece_type, placement, hold: from_hold });
+                let child = PcNode {
+                    board: next_board,
+                    lines_left: node.lines_left - clear_info.lines_cleared,
+                    index: next_index,
+                    hold: next_hold,
+                };
+                if self.search(engine, &child, pieces, steps, failed) {
+                    return true;
+                }
+                steps.pop();
+            }
+        }
+
+        failed.insert(key);
+        false
+    }
+}
+
+// Number of rows from the floor up to the highest filled cell
+fn stack_height(board: &Board) -> usize {
+    board.rows.iter()
+        .position(|&row| row != 0)
+        .map_or(0, |top| BOARD_HEIGHT - top)
+}
+
+fn filled_cells(board: &Board) -> usize {
+    board.rows.iter().map(|row| row.count_ones() as usize).sum()
+}
+
+// Every block of the piece must stay inside the bottom `lines_left` rows
+fn fits_below(piece: &Piece, lines_left: usize) -> bool {
+    let Some(mask) = piece.get_mask() else { return false };
+    let limit = (BOARD_HEIGHT - lines_left) as i32;
+    mask.iter()
+        .enumerate()
+        .all(|(i, &row_mask)| row_mask == 0 || piece.y + i as i32 >= limit)
+}
+
+// Pack the bottom rows into one word; at most 6 rows of 10 bits are in play.
+fn region_key(board: &Board, lines_left: usize) -> u64 {
+    board.rows[BOARD_HEIGHT - lines_left..]
+        .iter()
+        .fold(0u64, |key, &row| (key << BOARD_WIDTH) | row as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::PieceType::*;
+
+    #[test]
+    fn solutions_clear_the_board() {
+        // A 4x2 hole: two O's fill it, two S's cannot
+        let board: Board = "######....\n######....".parse().unwrap();
+        let solver = PerfectClearSolver::new(4);
+        let solution = solver.solve(&board, O, None, &[O]).unwrap();
+        assert_eq!(solution.height, 2);
+
+        let mut cleared = board.clone();
+        for step in &solution.steps {
+            cleared.lock_piece(&Piece::new(step.piece, step.placement.x, step.placement.y).with_rotation(step.placement.rotation));
+            cleared.clear_lines();
+        }
+        assert_eq!(cleared, Board::new());
+
+        assert!(solver.solve(&board, S, None, &[S]).is_none());
+        // With an O in hold, the S is swapped out and both O's fill the hole
+        let solution = solver.solve(&board, S, Some(O), &[O]).unwrap();
+        assert!(solution.steps[0].hold);
+    }
+
+    #[test]
+    fn tries_every_height_for_probability() {
+        // Ten pieces fill four lines; two lines would take five. The tenth is
+        // the last piece of its bag, so it is the only order to try.
+        let known = [I, O, T, S, Z, J, L, I, O];
+        let solver = PerfectClearSolver::new(4).with_hold(false);
+        assert_eq!(solver.heights(&Board::new()), vec![(2, 5), (4, 10)]);
+
+        let odds = solver.probability(&Board::new(), None, &known, BagRule::SevenBag, BagState::from_remaining(&[T]));
+        assert_eq!((odds.successes, odds.total), (1, 1));
+        let example = odds.example.unwrap();
+        assert_eq!(example.queue.len(), 10);
+        assert_eq!(example.solution.height, 4);
+    }
+}
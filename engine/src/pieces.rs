@@ -1,13 +1,16 @@
-use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use crate::board::BOARD_WIDTH;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PieceType {
     I = 0, O = 1, T = 2, S = 3, Z = 4, J = 5, L = 6,
 }
 
 impl PieceType {
+    pub const ALL: [PieceType; 7] = [
+        PieceType::I, PieceType::O, PieceType::T, PieceType::S, PieceType::Z, PieceType::J, PieceType::L,
+    ];
+
     pub fn from_i32(value: i32) -> Option<Self> {
         match value {
             0 => Some(PieceType::I),
@@ -29,7 +32,7 @@ impl PieceType {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Piece {
     pub piece_type: PieceType,
     pub x: i32,
@@ -52,8 +55,30 @@ impl Piece {
         Self::new(piece_type, x, y)
     }
 
+    // Row masks of the piece at its x, or None if it pokes through a wall
     pub fn get_mask(&self) -> Option<[u16; 4]> {
-        PIECE_MASKS.get(&(self.piece_type, self.rotation, self.x)).copied()
+        let piece = self.piece_type as usize;
+        let shape = PIECE_SHAPES[piece].get(self.rotation)?;
+        let (min_x, max_x) = PIECE_X_RANGE[piece][self.rotation];
+        if self.x < min_x || self.x > max_x {
+            return None;
+        }
+        Some(if self.x >= 0 { shape.map(|row| row << self.x) } else { shape.map(|row| row >> -self.x) })
+    }
+
+    // Board cells (x, y) the piece covers
+    pub fn cells(&self) -> [(i32, i32); 4] {
+        PIECE_CELLS[self.piece_type as usize][self.rotation % 4].map(|(dx, dy)| (self.x + dx as i32, self.y + dy as i32))
+    }
+
+    // SRS rotation centre within the piece's 4x4 box (y down). Rotations
+    // follow SRS order from spawn: north, east, south, west.
+    pub fn srs_centre(piece_type: PieceType, rotation: usize) -> (i32, i32) {
+        match piece_type {
+            PieceType::I => [(1, 1), (2, 1), (2, 2), (1, 2)][rotation % 4],
+            PieceType::O => [(1, 2), (1, 1), (2, 1), (2, 2)][rotation % 4],
+            _ => (1, 1),
+        }
     }
 
     pub fn with_rotation(&self, rotation: usize) -> Self {
@@ -84,104 +109,95 @@ impl Piece {
     }
 }
 
-// Pre-baked tetromino masks [piece][rotation][x] -> column mask
-type PieceMasks = HashMap<(PieceType, usize, i32), [u16; 4]>;
-
-static PIECE_MASKS: Lazy<PieceMasks> = Lazy::new(|| {
-    let mut masks = HashMap::new();
-    
-    // Base shapes for each piece
-    let base_shapes = [
-        // I piece
-        vec![
-            vec![(0, 1), (1, 1), (2, 1), (3, 1)], // Horizontal
-            vec![(2, 0), (2, 1), (2, 2), (2, 3)], // Vertical
-            vec![(0, 2), (1, 2), (2, 2), (3, 2)], // Horizontal
-            vec![(1, 0), (1, 1), (1, 2), (1, 3)], // Vertical
-        ],
-        // O piece (same for all rotations)
-        vec![
-            vec![(1, 1), (2, 1), (1, 2), (2, 2)]; 4
-        ],
-        // T piece
-        vec![
-            vec![(1, 0), (0, 1), (1, 1), (2, 1)], // Rotation 0 (Up)
-            vec![(1, 0), (1, 1), (1, 2), (2, 1)], // Rotation 1 (Right)
-            vec![(0, 1), (1, 1), (2, 1), (1, 2)], // Rotation 2 (Down)
-            vec![(1, 0), (0, 1), (1, 1), (1, 2)], // Rotation 3 (Left)
-        ],
-        // S piece
-        vec![
-            vec![(1, 0), (2, 0), (0, 1), (1, 1)],
-            vec![(1, 0), (1, 1), (2, 1), (2, 2)],
-            vec![(1, 1), (2, 1), (0, 2), (1, 2)],
-            vec![(0, 0), (0, 1), (1, 1), (1, 2)],
-        ],
-        // Z piece
-        vec![
-            vec![(0, 0), (1, 0), (1, 1), (2, 1)],
-            vec![(2, 0), (1, 1), (2, 1), (1, 2)],
-            vec![(0, 1), (1, 1), (1, 2), (2, 2)],
-            vec![(1, 0), (0, 1), (1, 1), (0, 2)],
-        ],
-        // J piece
-        vec![
-            vec![(0, 0), (0, 1), (1, 1), (2, 1)],
-            vec![(1, 0), (2, 0), (1, 1), (1, 2)],
-            vec![(0, 1), (1, 1), (2, 1), (2, 2)],
-            vec![(1, 0), (1, 1), (1, 2), (0, 2)],
-        ],
-        // L piece
-        vec![
-            vec![(2, 0), (0, 1), (1, 1), (2, 1)],
-            vec![(1, 0), (1, 1), (1, 2), (2, 2)],
-            vec![(0, 1), (1, 1), (2, 1), (0, 2)],
-            vec![(0, 0), (1, 0), (1, 1), (1, 2)],
-        ],
-    ];
-
-    for (piece_idx, piece_rotations) in base_shapes.iter().enumerate() {
-        let piece_type = match piece_idx {
-            0 => PieceType::I,
-            1 => PieceType::O,
-            2 => PieceType::T,
-            3 => PieceType::S,
-            4 => PieceType::Z,
-            5 => PieceType::J,
-            6 => PieceType::L,
-            _ => continue,
-        };
-
-        for (rotation, blocks) in piece_rotations.iter().enumerate() {
-            // Generate masks for all possible x positions
-            for x_offset in -3..=10 {
-                let mut mask = [0u16; 4];
-                let mut is_valid = true;
-
-                // Check if all blocks are within bounds before creating mask
-                for &(bx, _by) in blocks {
-                    let board_x = x_offset + bx as i32;
-                    if board_x < 0 || board_x >= BOARD_WIDTH as i32 {
-                        is_valid = false;
-                        break;
-                    }
-                }
-
-                if is_valid {
-                    for &(bx, by) in blocks {
-                        let board_x = x_offset + bx as i32;
-                        mask[by] |= 1 << board_x;
-                    }
-                    masks.insert((piece_type, rotation, x_offset), mask);
-                }
+// Cells (dx, dy) of each piece in its 4x4 box: [piece][rotation]
+const PIECE_CELLS: [[[(u8, u8); 4]; 4]; 7] = [
+    // I piece
+    [
+        [(0, 1), (1, 1), (2, 1), (3, 1)], // Horizontal
+        [(2, 0), (2, 1), (2, 2), (2, 3)], // Vertical
+        [(0, 2), (1, 2), (2, 2), (3, 2)], // Horizontal
+        [(1, 0), (1, 1), (1, 2), (1, 3)], // Vertical
+    ],
+    // O piece (same for all rotations)
+    [[(1, 1), (2, 1), (1, 2), (2, 2)]; 4],
+    // T piece
+    [
+        [(1, 0), (0, 1), (1, 1), (2, 1)], // Rotation 0 (Up)
+        [(1, 0), (1, 1), (1, 2), (2, 1)], // Rotation 1 (Right)
+        [(0, 1), (1, 1), (2, 1), (1, 2)], // Rotation 2 (Down)
+        [(1, 0), (0, 1), (1, 1), (1, 2)], // Rotation 3 (Left)
+    ],
+    // S piece
+    [
+        [(1, 0), (2, 0), (0, 1), (1, 1)],
+        [(1, 0), (1, 1), (2, 1), (2, 2)],
+        [(1, 1), (2, 1), (0, 2), (1, 2)],
+        [(0, 0), (0, 1), (1, 1), (1, 2)],
+    ],
+    // Z piece
+    [
+        [(0, 0), (1, 0), (1, 1), (2, 1)],
+        [(2, 0), (1, 1), (2, 1), (1, 2)],
+        [(0, 1), (1, 1), (1, 2), (2, 2)],
+        [(1, 0), (0, 1), (1, 1), (0, 2)],
+    ],
+    // J piece
+    [
+        [(0, 0), (0, 1), (1, 1), (2, 1)],
+        [(1, 0), (2, 0), (1, 1), (1, 2)],
+        [(0, 1), (1, 1), (2, 1), (2, 2)],
+        [(1, 0), (1, 1), (1, 2), (0, 2)],
+    ],
+    // L piece
+    [
+        [(2, 0), (0, 1), (1, 1), (2, 1)],
+        [(1, 0), (1, 1), (1, 2), (2, 2)],
+        [(0, 1), (1, 1), (2, 1), (0, 2)],
+        [(0, 0), (1, 0), (1, 1), (1, 2)],
+    ],
+];
+
+// Row masks of each shape at x = 0, baked at compile time: [piece][rotation][row]
+pub const PIECE_SHAPES: [[[u16; 4]; 4]; 7] = {
+    let mut shapes = [[[0u16; 4]; 4]; 7];
+    let mut piece = 0;
+    while piece < 7 {
+        let mut rotation = 0;
+        while rotation < 4 {
+            let mut cell = 0;
+            while cell < 4 {
+                let (dx, dy) = PIECE_CELLS[piece][rotation][cell];
+                shapes[piece][rotation][dy as usize] |= 1 << dx;
+                cell += 1;
             }
+            rotation += 1;
+        }
+        piece += 1;
+    }
+    shapes
+};
+
+// x range that keeps each shape inside the walls: [piece][rotation]
+const PIECE_X_RANGE: [[(i32, i32); 4]; 7] = {
+    let mut ranges = [[(0, 0); 4]; 7];
+    let mut piece = 0;
+    while piece < 7 {
+        let mut rotation = 0;
+        while rotation < 4 {
+            let shape = PIECE_SHAPES[piece][rotation];
+            let columns = shape[0] | shape[1] | shape[2] | shape[3];
+            let left = columns.trailing_zeros() as i32;
+            let right = 15 - columns.leading_zeros() as i32;
+            ranges[piece][rotation] = (-left, BOARD_WIDTH as i32 - 1 - right);
+            rotation += 1;
         }
+        piece += 1;
     }
-    masks
-});
+    ranges
+};
 
 // Placement position for each piece type and rotation
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Placement {
     pub x: i32,
     pub y: i32,
@@ -192,10 +208,4 @@ impl Placement {
     pub fn new(x: i32, y: i32, rotation: usize) -> Self {
         Self { x, y, rotation }
     }
-}
-
-impl Default for Placement {
-    fn default() -> Self {
-        Self { x: 0, y: 0, rotation: 0 }
-    }
 } 
\ No newline at end of file
@@ -0,0 +1,173 @@
+use std::fmt::Write;
+use crate::board::{Board, CellColor, BOARD_HEIGHT, BOARD_WIDTH, VISIBLE_HEIGHT};
+use crate::pieces::{Piece, PieceType};
+
+// Guideline colors by `PieceType`
+pub const PIECE_COLORS: [&str; 7] = ["#31c7ef", "#f7d308", "#ad4d9c", "#42b642", "#ef2029", "#5a65ad", "#ef7921"];
+const STACK_COLOR: &str = "#8c8c8c";
+const GARBAGE_COLOR: &str = "#5e5e5e";
+const NEW_COLOR: &str = "#e6e6e6";
+const EMPTY_COLOR: &str = "#101014";
+const GRID_COLOR: &str = "#26262e";
+const SVG_CELL: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    // '#' filled, or on a board with colors its piece letter or 'G' for
+    // garbage; piece letter for the highlighted piece ('X' for cells new
+    // since `previous`), '+' ghost, '.' empty
+    Ascii,
+    // '█' filled, '▓' highlighted, '░' ghost, '·' empty, as `display_board`
+    Unicode,
+}
+
+impl Charset {
+    fn glyph(self, cell: Cell) -> char {
+        match (self, cell) {
+            (Charset::Ascii, Cell::Empty) => '.',
+            (Charset::Ascii, Cell::Filled(CellColor::Unknown)) => '#',
+            (Charset::Ascii, Cell::Filled(CellColor::Garbage)) => 'G',
+            (Charset::Ascii, Cell::Filled(CellColor::Piece(piece))) => piece_letter(piece),
+            (Charset::Ascii, Cell::New) => 'X',
+            (Charset::Ascii, Cell::Piece(piece)) => piece_letter(piece),
+            (Charset::Ascii, Cell::Ghost(_)) => '+',
+            (Charset::Unicode, Cell::Empty) => '·',
+            (Charset::Unicode, Cell::Filled(_)) => '█',
+            (Charset::Unicode, Cell::New | Cell::Piece(_)) => '▓',
+            (Charset::Unicode, Cell::Ghost(_)) => '░',
+        }
+    }
+}
+
+pub fn piece_letter(piece: PieceType) -> char {
+    ['I', 'O', 'T', 'S', 'Z', 'J', 'L'][piece as usize]
+}
+
+// What to draw on top of the stack. Starts with the visible 20 rows in ASCII.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderOptions<'a> {
+    pub charset: Charset,
+    // Rows above the visible field to include, up to 20
+    pub hidden_rows: usize,
+    pub piece: Option<Piece>,
+    pub ghost: bool,
+    // Cells filled here but not in `previous` are drawn as highlighted
+    pub previous: Option<&'a Board>,
+}
+
+impl Default for RenderOptions<'_> {
+    fn default() -> Self {
+        Self { charset: Charset::Ascii, hidden_rows: 0, piece: None, ghost: false, previous: None }
+    }
+}
+
+impl<'a> RenderOptions<'a> {
+    pub fn with_charset(self, charset: Charset) -> Self {
+        Self { charset, ..self }
+    }
+
+    pub fn with_hidden_rows(self, hidden_rows: usize) -> Self {
+        Self { hidden_rows: hidden_rows.min(BOARD_HEIGHT - VISIBLE_HEIGHT), ..self }
+    }
+
+    // Highlight `piece`, and show where it would land if `ghost` is set
+    pub fn with_piece(self, piece: Piece, ghost: bool) -> Self {
+        Self { piece: Some(piece), ghost, ..self }
+    }
+
+    pub fn with_previous(self, previous: &'a Board) -> Self {
+        Self { previous: Some(previous), ..self }
+    }
+
+    fn first_row(&self) -> usize {
+        BOARD_HEIGHT - VISIBLE_HEIGHT - self.hidden_rows
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cell {
+    Empty,
+    // Unknown unless the board tracks colors
+    Filled(CellColor),
+    // Filled since `previous`; the piece that did it is unknown
+    New,
+    Piece(PieceType),
+    Ghost(PieceType),
+}
+
+impl Board {
+    // Cell grid for the rows `options` asks for, top row first
+    fn cells(&self, options: &RenderOptions) -> Vec<[Cell; BOARD_WIDTH]> {
+        let mut grid: Vec<[Cell; BOARD_WIDTH]> = (options.first_row()..BOARD_HEIGHT)
+            .map(|y| {
+                std::array::from_fn(|x| match (self.get_cell(x, y), options.previous) {
+                    (false, _) => Cell::Empty,
+                    (true, Some(previous)) if !previous.get_cell(x, y) => Cell::New,
+                    (true, _) => Cell::Filled(self.color(x, y).unwrap_or_default()),
+                })
+            })
+            .collect();
+
+        let mut draw = |piece: &Piece, cell: Cell| {
+            for (x, y) in piece.cells() {
+                let row = y - options.first_row() as i32;
+                if (0..BOARD_WIDTH as i32).contains(&x) && (0..grid.len() as i32).contains(&row) {
+                    let slot = &mut grid[row as usize][x as usize];
+                    if *slot == Cell::Empty || matches!(cell, Cell::Piece(_)) {
+                        *slot = cell;
+                    }
+                }
+            }
+        };
+        if let Some(piece) = options.piece {
+            if options.ghost {
+                let mut ghost = piece;
+                while self.can_place_piece(&ghost.moved(0, 1)) {
+                    ghost = ghost.moved(0, 1);
+                }
+                draw(&ghost, Cell::Ghost(piece.piece_type));
+            }
+            draw(&piece, Cell::Piece(piece.piece_type));
+        }
+        grid
+    }
+
+    // One line per row, top first, with no trailing newline
+    pub fn render(&self, options: &RenderOptions) -> String {
+        self.cells(options)
+            .iter()
+            .map(|row| row.iter().map(|&cell| options.charset.glyph(cell)).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Standalone SVG document, one square per cell
+    pub fn to_svg(&self, options: &RenderOptions) -> String {
+        let grid = self.cells(options);
+        let (width, height) = (BOARD_WIDTH * SVG_CELL, grid.len() * SVG_CELL);
+        let mut svg = String::new();
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#);
+        let _ = writeln!(svg, r#"  <rect width="{width}" height="{height}" fill="{EMPTY_COLOR}"/>"#);
+        for (row, cells) in grid.iter().enumerate() {
+            for (x, &cell) in cells.iter().enumerate() {
+                let (fill, opacity) = match cell {
+                    Cell::Empty => continue,
+                    Cell::Filled(CellColor::Unknown) => (STACK_COLOR, 1.0),
+                    Cell::Filled(CellColor::Garbage) => (GARBAGE_COLOR, 1.0),
+                    Cell::Filled(CellColor::Piece(piece)) => (PIECE_COLORS[piece as usize], 1.0),
+                    Cell::New => (NEW_COLOR, 1.0),
+                    Cell::Piece(piece) => (PIECE_COLORS[piece as usize], 1.0),
+                    Cell::Ghost(piece) => (PIECE_COLORS[piece as usize], 0.3),
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"  <rect x="{}" y="{}" width="{SVG_CELL}" height="{SVG_CELL}" fill="{fill}" fill-opacity="{opacity}" stroke="{GRID_COLOR}"/>"#,
+                    x * SVG_CELL,
+                    row * SVG_CELL
+                );
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
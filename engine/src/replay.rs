@@ -0,0 +1,377 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::evaluation::Strategy;
+use crate::movegen::Input;
+use crate::pieces::{PieceType, Placement};
+use crate::search::SearchEngine;
+use crate::sim::{Game, Handling, Ruleset};
+
+// Bumped whenever the layout changes; replays of another version are refused
+// rather than misread
+pub const REPLAY_VERSION: u32 = 2;
+// Frames count at this rate from the first spawn
+pub const FRAME_RATE: u32 = 60;
+// Binary replays open with this and a version byte, then the postcard body
+const MAGIC: &[u8; 3] = b"ZSR";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayInput {
+    Move(Input),
+    Hold,
+    HardDrop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    // One key press
+    Input { frame: u32, input: ReplayInput },
+    // A whole piece at once, as bots play: the active piece, which must be
+    // `piece`, locks at `placement`
+    Placement { frame: u32, piece: PieceType, placement: Placement },
+}
+
+// Why a game refused an event
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayFault {
+    // Stamped before the event ahead of it
+    OutOfOrder,
+    // The game had already topped out
+    GameOver,
+    // A move or rotation into a wall or the stack
+    Blocked(Input),
+    HoldDisabled,
+    PlacementsDisabled,
+    // Hold was already used for this piece
+    SecondHold,
+    WrongPiece { expected: PieceType, found: PieceType },
+    Unreachable(Placement),
+}
+
+impl fmt::Display for ReplayFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayFault::OutOfOrder => write!(f, "event out of frame order"),
+            ReplayFault::GameOver => write!(f, "event after the game ended"),
+            ReplayFault::Blocked(input) => write!(f, "{} is blocked", input.action()),
+            ReplayFault::HoldDisabled => write!(f, "hold is off in these rules"),
+            ReplayFault::PlacementsDisabled => write!(f, "whole-piece placements are off in these rules"),
+            ReplayFault::SecondHold => write!(f, "second hold for one piece"),
+            ReplayFault::WrongPiece { expected, found } => write!(f, "placement is for {:?} but {:?} is active", found, expected),
+            ReplayFault::Unreachable(placement) => write!(f, "the piece cannot reach {:?}", placement),
+        }
+    }
+}
+
+impl ReplayEvent {
+    pub fn frame(&self) -> u32 {
+        match *self {
+            ReplayEvent::Input { frame, .. } | ReplayEvent::Placement { frame, .. } => frame,
+        }
+    }
+
+    // Plays the event on `game`, or says why the game refuses it
+    pub fn apply(&self, game: &mut Game) -> Result<(), ReplayFault> {
+        let Some(current) = game.current() else {
+            return Err(ReplayFault::GameOver);
+        };
+        match *self {
+            ReplayEvent::Input { input: ReplayInput::Move(input), .. } => game.input(input).then_some(()).ok_or(ReplayFault::Blocked(input)),
+            ReplayEvent::Input { input: ReplayInput::Hold, .. } => {
+                if game.hold() {
+                    Ok(())
+                } else if !game.rules().hold {
+                    Err(ReplayFault::HoldDisabled)
+                } else {
+                    Err(ReplayFault::SecondHold)
+                }
+            }
+            ReplayEvent::Input { input: ReplayInput::HardDrop, .. } => {
+                let landing = game.landing().ok_or(ReplayFault::GameOver)?;
+                game.hard_drop().map(|_| ()).ok_or(ReplayFault::Unreachable(landing))
+            }
+            ReplayEvent::Placement { .. } if !game.rules().placements => Err(ReplayFault::PlacementsDisabled),
+            ReplayEvent::Placement { piece, .. } if piece != current => Err(ReplayFault::WrongPiece { expected: current, found: piece }),
+            ReplayEvent::Placement { placement, .. } => game.play(&placement).then_some(()).ok_or(ReplayFault::Unreachable(placement)),
+        }
+    }
+}
+
+// A whole game: the seed and rules deal the same pieces again, and the
+// events, in frame order, play them the same way
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub rules: Ruleset,
+    pub handling: Handling,
+    pub events: Vec<ReplayEvent>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    Version(u32),
+    Malformed(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Version(version) => write!(f, "replay version {} is not supported (expected {})", version, REPLAY_VERSION),
+            ReplayError::Malformed(reason) => write!(f, "malformed replay: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    pub fn new(seed: u64, rules: Ruleset, handling: Handling) -> Self {
+        Self { version: REPLAY_VERSION, seed, rules, handling, events: Vec::new() }
+    }
+
+    // Frame of the last event, the length of the game as recorded
+    pub fn frames(&self) -> u32 {
+        self.events.last().map_or(0, ReplayEvent::frame)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ReplayError> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+        let malformed = |error: serde_json::Error| ReplayError::Malformed(error.to_string());
+        let header: Header = serde_json::from_str(json).map_err(malformed)?;
+        if header.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(header.version));
+        }
+        serde_json::from_str(json).map_err(malformed)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(self.version as u8);
+        bytes.extend(postcard::to_allocvec(self).unwrap_or_default());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let body = bytes.strip_prefix(MAGIC).ok_or_else(|| ReplayError::Malformed("not a binary replay".to_string()))?;
+        let (&version, body) = body.split_first().ok_or_else(|| ReplayError::Malformed("no version".to_string()))?;
+        if version as u32 != REPLAY_VERSION {
+            return Err(ReplayError::Version(version as u32));
+        }
+        let replay: Replay = postcard::from_bytes(body).map_err(|error| ReplayError::Malformed(error.to_string()))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+        Ok(replay)
+    }
+
+    // Either encoding, told apart by the binary header
+    pub fn load(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.starts_with(MAGIC) {
+            return Self::from_bytes(bytes);
+        }
+        let json = std::str::from_utf8(bytes).map_err(|error| ReplayError::Malformed(error.to_string()))?;
+        Self::from_json(json)
+    }
+}
+
+// Plays a game and keeps every event the game accepted, stamped with the
+// frame the host has advanced to
+pub struct Recorder {
+    game: Game,
+    replay: Replay,
+    frame: u32,
+}
+
+impl Recorder {
+    pub fn new(seed: u64, rules: Ruleset, handling: Handling) -> Self {
+        Self { game: Game::with_rules(seed, rules), replay: Replay::new(seed, rules, handling), frame: 0 }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // The clock never runs backwards
+    pub fn advance_to(&mut self, frame: u32) {
+        self.frame = self.frame.max(frame);
+    }
+
+    pub fn advance(&mut self, frames: u32) {
+        self.frame = self.frame.saturating_add(frames);
+    }
+
+    fn record(&mut self, event: ReplayEvent) -> bool {
+        let accepted = event.apply(&mut self.game).is_ok();
+        if accepted {
+            self.replay.events.push(event);
+        }
+        accepted
+    }
+
+    pub fn input(&mut self, input: ReplayInput) -> bool {
+        self.record(ReplayEvent::Input { frame: self.frame, input })
+    }
+
+    // Lock the active piece at `placement` in one event
+    pub fn place(&mut self, placement: Placement) -> bool {
+        let Some(piece) = self.game.current() else {
+            return false;
+        };
+        self.record(ReplayEvent::Placement { frame: self.frame, piece, placement })
+    }
+
+    // Let the bot play up to `max_pieces`, a frame apart; returns how many it
+    // placed
+    pub fn play_bot(&mut self, search: &mut SearchEngine, strategy: Strategy, max_pieces: usize) -> usize {
+        let mut placed = 0;
+        while placed < max_pieces {
+            match self.game.bot_placement(search, strategy) {
+                Some(placement) if self.place(placement) => placed += 1,
+                _ => break,
+            }
+            self.advance(1);
+        }
+        placed
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+// Where a replay stopped matching the game: the frame, the index of the
+// event the game refused and why
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Divergence {
+    pub frame: u32,
+    pub event: usize,
+    pub fault: ReplayFault,
+}
+
+// Re-simulates a replay on a fresh game, one frame at a time
+pub struct ReplayPlayer<'a> {
+    replay: &'a Replay,
+    game: Game,
+    frame: u32,
+    next: usize,
+}
+
+impl<'a> ReplayPlayer<'a> {
+    pub fn new(replay: &'a Replay) -> Self {
+        Self { replay, game: Game::with_rules(replay.seed, replay.rules), frame: 0, next: 0 }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // The next frame `step` plays
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.replay.events.len()
+    }
+
+    // Plays every event stamped with the current frame, then moves on a frame
+    pub fn step(&mut self) -> Result<(), Divergence> {
+        while let Some(event) = self.replay.events.get(self.next) {
+            if event.frame() > self.frame {
+                break;
+            }
+            let applied = if event.frame() < self.frame { Err(ReplayFault::OutOfOrder) } else { event.apply(&mut self.game) };
+            if let Err(fault) = applied {
+                return Err(Divergence { frame: self.frame, event: self.next, fault });
+            }
+            self.next += 1;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    // Plays to the end, skipping frames without events
+    pub fn run(mut self) -> Result<Game, Divergence> {
+        while let Some(event) = self.replay.events.get(self.next) {
+            self.frame = self.frame.max(event.frame());
+            self.step()?;
+        }
+        Ok(self.game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    // A few bot pieces, then one played key by key
+    fn recorded() -> (Replay, Board) {
+        let mut recorder = Recorder::new(7, Ruleset::default(), Handling::default());
+        assert_eq!(recorder.play_bot(&mut SearchEngine::new(), Strategy::Balanced, 5), 5);
+        recorder.advance(10);
+        assert!(recorder.input(ReplayInput::Hold));
+        assert!(!recorder.input(ReplayInput::Hold));
+        recorder.advance(3);
+        assert!(recorder.input(ReplayInput::Move(Input::DasLeft)));
+        assert!(recorder.input(ReplayInput::HardDrop));
+        let board = recorder.game().board().clone();
+        (recorder.finish(), board)
+    }
+
+    #[test]
+    fn replays_the_recorded_game() {
+        let (replay, board) = recorded();
+        assert_eq!(replay.events.len(), 8);
+        assert_eq!(replay.frames(), 18);
+        let game = ReplayPlayer::new(&replay).run().unwrap();
+        assert_eq!(game.board(), &board);
+        assert_eq!(game.placements().len(), 6);
+    }
+
+    #[test]
+    fn round_trips_both_encodings() {
+        let (replay, _) = recorded();
+        assert_eq!(Replay::from_json(&replay.to_json()), Ok(replay.clone()));
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay.clone()));
+        assert_eq!(Replay::load(replay.to_json().as_bytes()), Ok(replay.clone()));
+        assert_eq!(Replay::load(&replay.to_bytes()), Ok(replay));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let (mut replay, _) = recorded();
+        replay.version = REPLAY_VERSION + 1;
+        assert_eq!(Replay::from_json(&replay.to_json()), Err(ReplayError::Version(REPLAY_VERSION + 1)));
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()), Err(ReplayError::Version(REPLAY_VERSION + 1)));
+        assert!(matches!(Replay::load(b"not a replay"), Err(ReplayError::Malformed(_))));
+    }
+
+    #[test]
+    fn reports_where_a_replay_diverges() {
+        let (mut replay, _) = recorded();
+        let ReplayEvent::Placement { piece, .. } = &mut replay.events[2] else {
+            panic!("the bot plays whole placements");
+        };
+        let expected = *piece;
+        let found = if expected == PieceType::I { PieceType::O } else { PieceType::I };
+        *piece = found;
+        let divergence = ReplayPlayer::new(&replay).run().err();
+        assert_eq!(divergence, Some(Divergence { frame: 2, event: 2, fault: ReplayFault::WrongPiece { expected, found } }));
+    }
+}
@@ -1,7 +1,51 @@
-use crate::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashSet;
+use serde::Serialize;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use crate::board::Board;
 use crate::pieces::{PieceType, Piece, Placement};
 use crate::console_log;
-use crate::evaluation::{Strategy, EvaluationWeights};
+use crate::evaluation::{Evaluation, EvaluationWeights, SpinKind, Strategy};
+use crate::pc::{PcStep, PerfectClearSolver};
+use crate::bag::{BagRule, BagState};
+use crate::mcts::MctsSearch;
+use crate::anytime::{AnytimeSearch, SearchBudget};
+use crate::transposition::TranspositionTable;
+use crate::zobrist;
+use crate::movegen::{self, MoveMap};
+
+pub const DEFAULT_BEAM_WIDTH: usize = 6;
+pub const DEFAULT_CHANCE_DEPTH: usize = 1;
+// Value of a position where the next piece has nowhere to go
+pub(crate) const NO_PLACEMENT_SCORE: f64 = -1.0e9;
+
+// Cells covered by `placement`, sorted so equivalent rotations compare equal
+pub(crate) fn placement_cells(piece_type: PieceType, placement: &Placement) -> [(i32, i32); 4] {
+    let mut cells = Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation).cells();
+    cells.sort_unstable();
+    cells
+}
+
+// Map `items` on the rayon pool natively; wasm32 has no threads, so there it
+// (and with `parallel` off) runs serially. Output order matches input order,
+// so callers see the same results either way.
+pub(crate) fn parallel_map<T: Sync, R: Send>(parallel: bool, items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if parallel {
+        return items.par_iter().map(f).collect();
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = parallel;
+    items.iter().map(f).collect()
+}
+
+// How the root placement is chosen; both use the same generator and evaluator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchBackend {
+    Beam,
+    Mcts { iterations: usize },
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct SearchResult {
@@ -11,95 +55,458 @@ pub struct SearchResult {
 #[derive(Clone, Debug)]
 pub struct PlacementEvaluation {
     pub score: f64,
+    // Line-clear and spin reward, already part of `score`
+    pub reward: f64,
     pub predicted_board: Board,
+    pub lines_cleared: usize,
+    pub spin: SpinKind,
+}
+
+// One candidate from `top_placements`, in engine coordinates (row 20 is the
+// top visible row)
+#[derive(Clone, Debug, Serialize)]
+pub struct RankedPlacement {
+    pub placement: Placement,
+    pub cells: [(i32, i32); 4],
+    // Static score of the placement alone, and with lookahead
+    pub score: f64,
+    pub value: f64,
+    pub lines_cleared: usize,
+    pub spin: SpinKind,
+    pub moves: Vec<String>,
+    // Visible rows after line clears, as `Board::to_flat_array`
+    pub board: Vec<i32>,
+}
+
+// A board plus the combo and back-to-back streak that led to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchState {
+    pub board: Board,
+    pub combo: u32,
+    pub b2b: bool,
+}
+
+impl SearchState {
+    pub fn new(board: Board) -> Self {
+        Self { board, combo: 0, b2b: false }
+    }
+
+    // Mid-streak, as a frontend that tracks its own combo reports it
+    pub fn with_streak(self, combo: u32, b2b: bool) -> Self {
+        Self { combo, b2b, ..self }
+    }
+
+    // Tetrises and spin clears keep back-to-back going, as in `Game::score`
+    pub fn after(&self, eval: &PlacementEvaluation) -> Self {
+        let (combo, b2b) = match eval.lines_cleared {
+            0 => (0, self.b2b),
+            lines => (self.combo + 1, lines >= 4 || eval.spin != SpinKind::None),
+        };
+        Self { board: eval.predicted_board.clone(), combo, b2b }
+    }
+
+    // The bot does not model hold, so the slot is always empty
+    pub fn hash(&self, queue: &[PieceType]) -> u64 {
+        zobrist::state_hash(&self.board, None, queue, self.combo, self.b2b)
+    }
 }
 
-pub struct SearchEngine {}
+pub struct SearchEngine {
+    perfect_clear: Option<PerfectClearSolver>,
+    backend: SearchBackend,
+    // Placements expanded per decision node during lookahead
+    beam_width: usize,
+    // Unknown pieces searched past the preview, weighted by the bag
+    chance_depth: usize,
+    bag_rule: BagRule,
+    // What the bag still owes after the last known piece
+    bag: BagState,
+    // Placement generations so far, used for node budgets
+    nodes_searched: AtomicUsize,
+    // Spread evaluation and subtrees over threads (native only)
+    parallel: bool,
+    // Static scores and subtree values, kept between calls
+    table: TranspositionTable,
+    // Lookahead tree kept from one piece to the next
+    tree: Option<AnytimeSearch>,
+}
+
+impl Default for SearchEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl SearchEngine {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            perfect_clear: None,
+            backend: SearchBackend::Beam,
+            beam_width: DEFAULT_BEAM_WIDTH,
+            chance_depth: DEFAULT_CHANCE_DEPTH,
+            bag_rule: BagRule::SevenBag,
+            bag: BagState::full(),
+            nodes_searched: AtomicUsize::new(0),
+            parallel: true,
+            table: TranspositionTable::default(),
+            tree: None,
+        }
+    }
+
+    pub fn set_backend(&mut self, backend: SearchBackend) {
+        self.backend = backend;
+    }
+
+    pub fn set_lookahead(&mut self, beam_width: usize, chance_depth: usize) {
+        self.beam_width = beam_width.max(1);
+        self.chance_depth = chance_depth;
+        self.tree = None; // Built with the old beam
+    }
+
+    pub fn set_bag(&mut self, bag_rule: BagRule, bag: BagState) {
+        self.bag_rule = bag_rule;
+        self.bag = bag;
+    }
+
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    pub fn is_parallel(&self) -> bool {
+        self.parallel
+    }
+
+    pub fn set_table_capacity(&mut self, entries: usize) {
+        self.table = TranspositionTable::new(entries);
+    }
+
+    pub fn clear_table(&self) {
+        self.table.clear();
+    }
+
+    pub fn beam_width(&self) -> usize {
+        self.beam_width
+    }
+
+    pub fn chance_depth(&self) -> usize {
+        self.chance_depth
     }
 
-    pub fn search(&mut self, board: &Board, current_piece: PieceType, _next_piece: Option<PieceType>, strategy: Strategy, arr: u32, das: u32, debug: bool) -> SearchResult {
+    pub fn bag_rule(&self) -> BagRule {
+        self.bag_rule
+    }
+
+    pub fn bag(&self) -> BagState {
+        self.bag
+    }
+
+    pub fn nodes_searched(&self) -> usize {
+        self.nodes_searched.load(Ordering::Relaxed)
+    }
+
+    // The bot does not know what the game holds, so PC mode plays without hold.
+    pub fn set_perfect_clear(&mut self, solver: Option<PerfectClearSolver>) {
+        self.perfect_clear = solver.map(|solver| solver.with_hold(false));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(&mut self, board: &Board, current_piece: PieceType, queue: &[PieceType], strategy: Strategy, arr: u32, das: u32, debug: bool) -> SearchResult {
+        if let Some(best_move) = self.find_perfect_clear_move(board, current_piece, queue, arr, das, debug) {
+            return SearchResult { best_move };
+        }
+
         let weights = EvaluationWeights::new(strategy);
-        let best_move = self.find_best_move_for_strategy(board, current_piece, &weights, arr, das, debug);
+        let best_move = self.find_best_move_for_strategy(board, current_piece, queue, &weights, arr, das, debug);
 
         SearchResult {
             best_move,
         }
     }
 
-    fn find_best_move_for_strategy(&self, board: &Board, current_piece: PieceType, weights: &EvaluationWeights, arr: u32, das: u32, debug: bool) -> String {
+    // Like `search`, but through the kept tree: whatever was searched below the
+    // last move is reused, the tree is deepened to the configured lookahead,
+    // and it is then re-rooted at the chosen placement so `think` can ponder
+    // the next piece while this one falls. MCTS keeps no tree.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search_with_tree(&mut self, board: &Board, current_piece: PieceType, queue: &[PieceType], strategy: Strategy, arr: u32, das: u32, debug: bool) -> SearchResult {
+        if let SearchBackend::Mcts { .. } = self.backend {
+            self.tree = None;
+            return self.search(board, current_piece, queue, strategy, arr, das, debug);
+        }
+
+        let reused = self.begin_tree(board, current_piece, queue, EvaluationWeights::new(strategy), debug);
+        let level = queue.len() + 1 + self.chance_depth;
+        let Some(mut tree) = self.tree.take() else {
+            return SearchResult { best_move: "hard_drop".to_string() };
+        };
+        tree.think_until(self, level);
+
+        let Some(best) = tree.best().cloned() else {
+            return SearchResult { best_move: "hard_drop".to_string() };
+        };
+        if debug {
+            console_log!("🌳 Tree {} ({} nodes, level {})", if reused { "reused" } else { "built" }, tree.node_count(), tree.level());
+            console_log!("🏆 WINNER: x={}, rot={} → SCORE={:.1}, LOOKAHEAD={:.1}", best.placement.x, best.placement.rotation, best.eval.score, best.value);
+            self.log_breakdown(board, current_piece, &best.placement, tree.weights());
+            best.eval.predicted_board.display_board("🎯 FINAL BOARD RESULT", Some(board));
+        }
+
+        if tree.commit(&best.placement) {
+            self.tree = Some(tree);
+        }
+        let move_sequence = self.generate_move_sequence(board, current_piece, &best.placement, arr, das);
+        SearchResult { best_move: move_sequence.join(",") }
+    }
+
+    // Point the kept tree at this position: reuse it if `board` is its root or
+    // one of its root placements and the queue only adds to what it knew,
+    // otherwise start over. Returns whether anything was reused.
+    pub fn begin_tree(&mut self, board: &Board, current_piece: PieceType, queue: &[PieceType], weights: EvaluationWeights, debug: bool) -> bool {
+        if let Some(step) = self.perfect_clear_step(board, current_piece, queue, debug) {
+            // A perfect clear beats anything deeper search could find
+            self.tree = Some(AnytimeSearch::solved(self, board, current_piece, queue, weights, step.placement));
+            return false;
+        }
+
+        let mut known = Vec::with_capacity(queue.len() + 1);
+        known.push(current_piece);
+        known.extend_from_slice(queue);
+
+        let reused = self.tree.take().filter(|tree| tree.weights().fingerprint() == weights.fingerprint()).and_then(|mut tree| {
+            let at_root = tree.board() == board || tree.commit_board(board);
+            (at_root && tree.reveal(&known, self.bag)).then_some(tree)
+        });
+
+        let was_reused = reused.is_some();
+        self.tree = Some(match reused {
+            Some(tree) => tree,
+            None => AnytimeSearch::new(self, board, current_piece, queue, weights),
+        });
+        was_reused
+    }
+
+    // Deepen the kept tree; returns true once there is nothing left to do
+    pub fn think(&mut self, budget: SearchBudget) -> bool {
+        let Some(mut tree) = self.tree.take() else {
+            return true;
+        };
+        let finished = tree.think(self, budget);
+        self.tree = Some(tree);
+        finished
+    }
+
+    pub fn tree(&self) -> Option<&AnytimeSearch> {
+        self.tree.as_ref()
+    }
+
+    // First placement of a perfect clear, when PC mode is on and one exists
+    pub(crate) fn perfect_clear_step(&self, board: &Board, current_piece: PieceType, queue: &[PieceType], debug: bool) -> Option<PcStep> {
+        let solver = self.perfect_clear.as_ref()?;
+        let solution = solver.solve(board, current_piece, None, queue)?;
+
+        if debug {
+            console_log!("🧹 Perfect clear in {} pieces ({} lines)", solution.steps.len(), solution.height);
+        }
+        solution.steps.first().copied()
+    }
+
+    fn find_perfect_clear_move(&self, board: &Board, current_piece: PieceType, queue: &[PieceType], arr: u32, das: u32, debug: bool) -> Option<String> {
+        let step = self.perfect_clear_step(board, current_piece, queue, debug)?;
+        let move_sequence = self.generate_move_sequence(board, step.piece, &step.placement, arr, das);
+        Some(move_sequence.join(","))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn find_best_move_for_strategy(&self, board: &Board, current_piece: PieceType, queue: &[PieceType], weights: &EvaluationWeights, arr: u32, das: u32, debug: bool) -> String {
         if debug {
             console_log!("🚀🚀🚀 === TETRIS AI ANALYSIS START === 🚀🚀🚀");
-            console_log!("🧩 Analyzing piece: {:?}", current_piece);
+            console_log!("🧩 Analyzing piece: {:?}, queue: {:?}, bag: {:?}", current_piece, queue, self.bag.pieces().collect::<Vec<_>>());
             board.display_board("📋 ORIGINAL BOARD STATE", None);
         }
 
-        let all_placements = self.generate_all_placements(board, current_piece);
+        let best = match self.backend {
+            SearchBackend::Beam => self.beam_search(board, current_piece, queue, weights),
+            SearchBackend::Mcts { iterations } => {
+                let seed = board.hash() ^ current_piece as u64;
+                MctsSearch::new(self, weights, self.bag_rule, seed).run(board, current_piece, queue, self.bag, iterations)
+            }
+        };
+        let Some((best_placement, best_eval, best_value)) = best else {
+            return "hard_drop".to_string();
+        };
+
+        if debug {
+            console_log!("🏆 WINNER: x={}, rot={} → SCORE={:.1}, LOOKAHEAD={:.1}", best_placement.x, best_placement.rotation, best_eval.score, best_value);
+            self.log_breakdown(board, current_piece, &best_placement, weights);
+            best_eval.predicted_board.display_board("🎯 FINAL BOARD RESULT", Some(board));
+        }
+
+        let move_sequence = self.generate_move_sequence(board, current_piece, &best_placement, arr, das);
+        move_sequence.join(",")
+    }
+
+    // Only the best few placements by static score are worth a lookahead
+    fn beam_search(&self, board: &Board, current_piece: PieceType, queue: &[PieceType], weights: &EvaluationWeights) -> Option<(Placement, PlacementEvaluation, f64)> {
+        let mut ranked = self.rank_placements(board, current_piece, weights);
+        ranked.truncate(self.beam_width);
+        self.lookahead(&SearchState::new(board.without_colors()), ranked, queue, weights).into_iter().next()
+    }
+
+    // Lookahead value of each candidate placed from `root`, best first. A value
+    // is every reward earned along the line plus the score of the board it
+    // ends on.
+    pub(crate) fn lookahead(&self, root: &SearchState, candidates: Vec<(Placement, PlacementEvaluation)>, queue: &[PieceType], weights: &EvaluationWeights) -> Vec<(Placement, PlacementEvaluation, f64)> {
+        let values = parallel_map(self.parallel, &candidates, |(_, eval)| {
+            eval.reward + self.expectimax(&root.after(eval), queue, self.bag, self.chance_depth, weights)
+        });
 
-        let mut all_evaluations: Vec<(Placement, PlacementEvaluation)> = all_placements
+        let mut ranked: Vec<_> = candidates
             .into_iter()
-            .filter_map(|placement| {
-                self.evaluate_placement(board, current_piece, &placement, weights)
-                    .map(|eval| (placement, eval))
-            })
+            .zip(values)
+            .map(|((placement, eval), value)| (placement, eval, value))
             .collect();
+        // Stable, so ties keep static-score order
+        ranked.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        ranked
+    }
 
-        all_evaluations.sort_by(|a, b| b.1.score.partial_cmp(&a.1.score).unwrap_or(std::cmp::Ordering::Equal));
+    // The `count` best placements by lookahead value, with what a UI needs to
+    // show them as hints. Rotations that cover the same cells are listed once,
+    // and lookahead covers at least a full beam of distinct placements.
+    #[allow(clippy::too_many_arguments)]
+    pub fn top_placements(&self, board: &Board, current_piece: PieceType, queue: &[PieceType], weights: &EvaluationWeights, count: usize, arr: u32, das: u32) -> Vec<RankedPlacement> {
+        self.top_placements_from(&SearchState::new(board.without_colors()), current_piece, queue, weights, count, arr, das)
+    }
 
-        if all_evaluations.is_empty() {
-            return "hard_drop".to_string();
-        }
+    // `top_placements` with the combo and back-to-back of `root` carried into
+    // the lookahead
+    #[allow(clippy::too_many_arguments)]
+    pub fn top_placements_from(&self, root: &SearchState, current_piece: PieceType, queue: &[PieceType], weights: &EvaluationWeights, count: usize, arr: u32, das: u32) -> Vec<RankedPlacement> {
+        let board = &root.board;
+        let mut candidates = self.distinct_placements(board, current_piece, weights);
+        candidates.truncate(count.max(self.beam_width));
 
-        let (best_placement, best_eval) = &all_evaluations[0];
+        let mut ranked = self.lookahead(root, candidates, queue, weights);
+        ranked.truncate(count);
+        ranked
+            .into_iter()
+            .map(|(placement, eval, value)| self.ranked_placement(board, current_piece, placement, eval, value, arr, das))
+            .collect()
+    }
 
-        if debug {
-            console_log!("🏆 WINNER: x={}, rot={} → SCORE={:.1}", best_placement.x, best_placement.rotation, best_eval.score);
-            best_eval.predicted_board.display_board("🎯 FINAL BOARD RESULT", Some(board));
+    // `rank_placements` with one entry per set of covered cells
+    pub(crate) fn distinct_placements(&self, board: &Board, piece_type: PieceType, weights: &EvaluationWeights) -> Vec<(Placement, PlacementEvaluation)> {
+        let mut seen = HashSet::new();
+        let mut ranked = self.rank_placements(board, piece_type, weights);
+        ranked.retain(|(placement, _)| seen.insert(placement_cells(piece_type, placement)));
+        ranked
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn ranked_placement(&self, board: &Board, piece_type: PieceType, placement: Placement, eval: PlacementEvaluation, value: f64, arr: u32, das: u32) -> RankedPlacement {
+        RankedPlacement {
+            placement,
+            cells: placement_cells(piece_type, &placement),
+            score: eval.score,
+            value,
+            lines_cleared: eval.lines_cleared,
+            spin: eval.spin,
+            moves: self.generate_move_sequence(board, piece_type, &placement, arr, das),
+            board: eval.predicted_board.to_flat_array(),
         }
+    }
 
-        let move_sequence = self.generate_move_sequence(board, current_piece, best_placement, arr, das);
-        let best_move = move_sequence.join(",");
+    fn log_breakdown(&self, board: &Board, piece_type: PieceType, placement: &Placement, weights: &EvaluationWeights) {
+        let Some(eval) = self.explain_placement(board, piece_type, placement, weights) else {
+            return;
+        };
+        for (name, feature) in [("height", eval.aggregate_height), ("max height", eval.max_height), ("bumpiness", eval.bumpiness), ("holes", eval.holes)] {
+            console_log!("   {:<10} {:>6.1} × {:>6.2} = {:>7.1}", name, feature.value, feature.weight, feature.contribution);
+        }
+        console_log!("   lines {} (+{:.1}), spin {:?} (+{:.1})", eval.lines_cleared, eval.line_clear_reward, eval.spin, eval.spin_reward);
+    }
+
+    // Every reachable placement with its static evaluation, best first. Too
+    // fine-grained to be worth threads; callers parallelize above it.
+    pub(crate) fn rank_placements(&self, board: &Board, piece_type: PieceType, weights: &EvaluationWeights) -> Vec<(Placement, PlacementEvaluation)> {
+        self.nodes_searched.fetch_add(1, Ordering::Relaxed);
+        let moves = MoveMap::new(board, piece_type);
+        let mut evaluations: Vec<(Placement, PlacementEvaluation)> = moves
+            .placements()
+            .into_iter()
+            .filter_map(|placement| self.score_placement(board, piece_type, &placement, moves.spin(&placement), weights).map(|eval| (placement, eval)))
+            .collect();
 
-        best_move
+        evaluations.sort_by(|a, b| b.1.score.partial_cmp(&a.1.score).unwrap_or(std::cmp::Ordering::Equal));
+        evaluations
     }
 
-    fn generate_all_placements(&self, board: &Board, piece_type: PieceType) -> Vec<Placement> {
-        let mut placements = Vec::new();
-        let mut visited = std::collections::HashSet::new();
-        
-        for rotation in 0..4 {
-            for x in -2..BOARD_WIDTH as i32 + 2 {
-                let piece = Piece::new(piece_type, x, 0).with_rotation(rotation);
-                if let Some(landing_y) = self.find_landing_position(board, &piece) {
-                    let placement = Placement::new(piece.x, landing_y, rotation);
-                    if visited.insert(placement) {
-                        placements.push(placement);
-                    }
-                }
-            }
+    // Known pieces are max nodes; past the preview each piece the bag can still
+    // deal is a chance outcome of equal weight. Runs on one thread: `lookahead`
+    // already spreads the root candidates over the pool.
+    // Subtree values are cached under the full position (board, preview, streak)
+    // plus everything else they depend on, so transpositions share one search.
+    // Values only count rewards earned from `state` on; callers add the reward
+    // of the placement that reached it, so the cache is path-independent.
+    pub(crate) fn expectimax(&self, state: &SearchState, queue: &[PieceType], bag: BagState, chance_depth: usize, weights: &EvaluationWeights) -> f64 {
+        if queue.is_empty() && chance_depth == 0 {
+            return self.static_score(&state.board, weights);
+        }
+
+        let key = state.hash(queue)
+            ^ zobrist::mix(bag.remaining as u64 | (chance_depth as u64) << 8 | (self.beam_width as u64) << 16 | (self.bag_rule as u64) << 56)
+            ^ weights.fingerprint();
+        if let Some(value) = self.table.probe(key) {
+            return value;
         }
-        placements
+
+        let value = if let Some((&piece, rest)) = queue.split_first() {
+            self.best_child_value(state, piece, rest, bag, chance_depth, weights)
+        } else {
+            let outcomes = self.bag_rule.candidates(&bag);
+            let total: f64 = outcomes
+                .iter()
+                .map(|&piece| self.best_child_value(state, piece, &[], bag.take(piece), chance_depth - 1, weights))
+                .sum();
+            total / outcomes.len() as f64
+        };
+
+        self.table.store(key, value);
+        value
     }
-    
-    fn find_landing_position(&self, board: &Board, piece: &Piece) -> Option<i32> {
-        let mut test_piece = piece.clone();
-        test_piece.y = 0;
-        
-        if !board.can_place_piece(&test_piece) {
-            return None;
+
+    fn best_child_value(&self, state: &SearchState, piece: PieceType, rest: &[PieceType], bag: BagState, chance_depth: usize, weights: &EvaluationWeights) -> f64 {
+        let ranked = self.rank_placements(&state.board, piece, weights);
+        if rest.is_empty() && chance_depth == 0 {
+            return ranked.first().map_or(NO_PLACEMENT_SCORE, |(_, eval)| eval.score);
         }
-        
-        while board.can_place_piece(&test_piece.moved(0, 1)) {
-            test_piece.y += 1;
-            if test_piece.y > BOARD_HEIGHT as i32 { return None; }
+
+        let beam = &ranked[..ranked.len().min(self.beam_width)];
+        beam.iter()
+            .map(|(_, eval)| eval.reward + self.expectimax(&state.after(eval), rest, bag, chance_depth, weights))
+            .fold(NO_PLACEMENT_SCORE, f64::max)
+    }
+
+    // Board evaluation through the table; keyed by board and weights only
+    pub(crate) fn static_score(&self, board: &Board, weights: &EvaluationWeights) -> f64 {
+        let key = board.hash() ^ weights.fingerprint();
+        if let Some(score) = self.table.probe(key) {
+            return score;
         }
-        Some(test_piece.y)
+        let score = board.evaluate(weights).score;
+        self.table.store(key, score);
+        score
+    }
+
+    pub(crate) fn generate_all_placements(&self, board: &Board, piece_type: PieceType) -> Vec<Placement> {
+        MoveMap::new(board, piece_type).placements()
+    }
+
+    pub(crate) fn evaluate_placement(&self, board: &Board, piece_type: PieceType, placement: &Placement, weights: &EvaluationWeights) -> Option<PlacementEvaluation> {
+        self.score_placement(board, piece_type, placement, movegen::spin_at(board, piece_type, placement), weights)
     }
 
-    fn evaluate_placement(&self, board: &Board, piece_type: PieceType, placement: &Placement, weights: &EvaluationWeights) -> Option<PlacementEvaluation> {
+    fn score_placement(&self, board: &Board, piece_type: PieceType, placement: &Placement, spin: SpinKind, weights: &EvaluationWeights) -> Option<PlacementEvaluation> {
         let piece = Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation);
         if !board.can_place_piece(&piece) {
             return None;
@@ -107,78 +514,105 @@ impl SearchEngine {
 
         let mut predicted_board = board.clone();
         predicted_board.lock_piece(&piece);
-        predicted_board.clear_lines();
-        
-        let _heights_after = predicted_board.get_heights();
+        let clear_info = predicted_board.clear_lines();
+        let reward = weights.line_clear_reward(clear_info.lines_cleared) + weights.spin_reward(clear_info.lines_cleared, spin);
 
         Some(PlacementEvaluation {
-            score: predicted_board.evaluate(weights).score,
+            score: self.static_score(&predicted_board, weights) + reward,
+            reward,
             predicted_board,
+            lines_cleared: clear_info.lines_cleared,
+            spin,
         })
     }
 
-    fn generate_move_sequence(&self, board: &Board, piece_type: PieceType, placement: &Placement, arr: u32, das: u32) -> Vec<String> {
-        use std::collections::{BinaryHeap, HashMap};
-        use std::cmp::Ordering;
+    // Full score breakdown of one placement, for explaining a decision. None
+    // if the piece cannot come to rest there.
+    pub fn explain_placement(&self, board: &Board, piece_type: PieceType, placement: &Placement, weights: &EvaluationWeights) -> Option<Evaluation> {
+        let piece = Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation);
+        if !board.can_place_piece(&piece) || board.can_place_piece(&piece.moved(0, 1)) {
+            return None;
+        }
+
+        let mut predicted_board = board.clone();
+        predicted_board.lock_piece(&piece);
+        let clear_info = predicted_board.clear_lines();
+        Some(predicted_board.evaluate(weights).with_rewards(clear_info.lines_cleared, movegen::spin_at(board, piece_type, placement), weights))
+    }
+
+    pub(crate) fn generate_move_sequence(&self, board: &Board, piece_type: PieceType, placement: &Placement, arr: u32, das: u32) -> Vec<String> {
+        MoveMap::new(board, piece_type)
+            .path(placement, arr, das)
+            .unwrap_or_else(|| vec!["hard_drop".to_string()])
+    }
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        #[derive(Clone, Eq, PartialEq)]
-        struct State { cost: usize, piece: Piece, path: Vec<String> }
+    // Four rows with the right column open
+    fn well() -> Board {
+        "#########.\n".repeat(4).parse().unwrap()
+    }
 
-        impl Ord for State {
-            fn cmp(&self, other: &Self) -> Ordering {
-                other.cost.cmp(&self.cost).then_with(|| self.path.len().cmp(&other.path.len()))
-            }
-        }
+    #[test]
+    fn spin_clears_keep_back_to_back() {
+        let state = SearchState::new(Board::new()).with_streak(2, true);
+        let eval = |lines_cleared, spin| PlacementEvaluation { score: 0.0, reward: 0.0, predicted_board: Board::new(), lines_cleared, spin };
+        assert_eq!(state.after(&eval(2, SpinKind::Full)), SearchState::new(Board::new()).with_streak(3, true));
+        assert!(state.after(&eval(1, SpinKind::Mini)).b2b);
+        assert!(!state.after(&eval(2, SpinKind::None)).b2b);
+        assert_eq!(state.after(&eval(0, SpinKind::None)), SearchState::new(Board::new()).with_streak(0, true));
+    }
 
-        impl PartialOrd for State {
-            fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
-        }
+    #[test]
+    fn repeated_positions_hit_the_table() {
+        let weights = EvaluationWeights::new(Strategy::Balanced);
+        let engine = SearchEngine::new();
+        let state = SearchState::new(well());
+        let queue = [PieceType::O, PieceType::T];
+        let value = engine.expectimax(&state, &queue, BagState::full(), 0, &weights);
+        let nodes = engine.nodes_searched();
+        let entries = engine.table.len();
+        assert!(nodes > 0);
 
-        let start_piece = Piece::spawn(piece_type);
-        let start_state = State { cost: 0, piece: start_piece.clone(), path: Vec::new() };
-        let mut open_set = BinaryHeap::new();
-        open_set.push(start_state);
-        let mut visited = HashMap::new();
-        visited.insert((start_piece.x, start_piece.rotation), 0);
+        assert_eq!(engine.expectimax(&state, &queue, BagState::full(), 0, &weights), value);
+        assert_eq!(engine.nodes_searched(), nodes);
+        assert_eq!(engine.table.len(), entries);
 
-        while let Some(State { cost, piece, path }) = open_set.pop() {
-            if piece.x == placement.x && piece.rotation == placement.rotation {
-                let mut final_path = path;
-                final_path.push("hard_drop".to_string());
-                return final_path;
-            }
+        // Another streak is another position
+        engine.expectimax(&state.clone().with_streak(1, true), &queue, BagState::full(), 0, &weights);
+        assert!(engine.nodes_searched() > nodes);
 
-            if cost > *visited.get(&(piece.x, piece.rotation)).unwrap_or(&usize::MAX) { continue; }
-
-            let mut moves: Vec<(Piece, &str, usize)> = Vec::new();
-            moves.push((piece.rotated(true), "rotate", 1));
-            moves.push((piece.rotated(false), "rotate_ccw", 1));
-            moves.push((piece.moved(-1, 0), "move_left", 1));
-            moves.push((piece.moved(1, 0), "move_right", 1));
-
-            if arr == 0 {
-                let mut left_piece = piece.clone();
-                while board.can_place_piece(&left_piece.moved(-1, 0)) { left_piece = left_piece.moved(-1, 0); }
-                if left_piece.x != piece.x { moves.push((left_piece, "move_all_the_way_left", das as usize)); }
-                
-                let mut right_piece = piece.clone();
-                while board.can_place_piece(&right_piece.moved(1, 0)) { right_piece = right_piece.moved(1, 0); }
-                if right_piece.x != piece.x { moves.push((right_piece, "move_all_the_way_right", das as usize)); }
-            }
+        engine.clear_table();
+        assert_eq!(engine.expectimax(&state, &queue, BagState::full(), 0, &weights), value);
+    }
 
-            for (next_piece, action, action_cost) in moves {
-                if board.can_place_piece(&next_piece) {
-                    let new_cost = cost + action_cost;
-                    let state_key = (next_piece.x, next_piece.rotation);
-                    if new_cost < *visited.get(&state_key).unwrap_or(&usize::MAX) {
-                        visited.insert(state_key, new_cost);
-                        let mut new_path = path.clone();
-                        new_path.push(action.to_string());
-                        open_set.push(State { cost: new_cost, piece: next_piece, path: new_path });
-                    }
-                }
-            }
+    #[test]
+    fn lookahead_credits_the_tetris() {
+        let weights = EvaluationWeights::new(Strategy::Aggressive);
+        for (queue, chance_depth) in [(vec![], 0), (vec![PieceType::O], 0), (vec![], 1)] {
+            let mut engine = SearchEngine::new();
+            engine.set_lookahead(DEFAULT_BEAM_WIDTH, chance_depth);
+            let best = engine.top_placements(&well(), PieceType::I, &queue, &weights, 1, 0, 0);
+            assert_eq!(best[0].lines_cleared, 4, "queue {:?}, chance depth {}", queue, chance_depth);
+            assert!(best[0].value > 0.0);
         }
-        vec!["hard_drop".to_string()]
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn tree_and_mcts_credit_the_tetris() {
+        let weights = EvaluationWeights::new(Strategy::Aggressive);
+        let mut engine = SearchEngine::new();
+        engine.begin_tree(&well(), PieceType::I, &[PieceType::O], weights.clone(), false);
+        let mut tree = engine.tree.take().unwrap();
+        tree.think_until(&engine, 3);
+        let best = tree.best().unwrap();
+        assert_eq!(best.eval.lines_cleared, 4);
+        assert!(best.value > 0.0);
+
+        let (_, eval, value) = MctsSearch::new(&engine, &weights, BagRule::SevenBag, 1).run(&well(), PieceType::I, &[PieceType::O], BagState::full(), 200).unwrap();
+        assert_eq!(eval.lines_cleared, 4);
+        assert!(value > 0.0);
+    }
+}
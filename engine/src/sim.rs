@@ -0,0 +1,334 @@
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use crate::bag::{BagRule, BagState, PieceRng};
+use crate::board::Board;
+use crate::evaluation::{SpinKind, Strategy};
+use crate::movegen::{self, Input, MoveMap};
+use crate::pieces::{Piece, PieceType, Placement};
+use crate::search::SearchEngine;
+
+// The web game shows the bot one next piece; every extra preview piece is
+// another full level of lookahead
+pub const DEFAULT_PREVIEW: usize = 1;
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct GameStats {
+    pub pieces: usize,
+    pub lines: usize,
+    // Guideline points at level 1 from line clears and spins; drops score
+    // nothing
+    pub score: u64,
+    // Locks by lines cleared, zero to four
+    pub clears: [usize; 5],
+    pub spins: usize,
+    pub mini_spins: usize,
+    pub perfect_clears: usize,
+}
+
+// One lock: which piece went where
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlacementRecord {
+    pub piece: PieceType,
+    pub placement: Placement,
+    // The piece was held first, swapping in `piece`
+    #[serde(default)]
+    pub hold: bool,
+}
+
+// How pieces are dealt, how many show and whether hold is allowed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ruleset {
+    pub bag: BagRule,
+    pub preview: usize,
+    pub hold: bool,
+    // Whole pieces may be locked in one step, as bots play, rather than moved
+    // input by input
+    pub placements: bool,
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self { bag: BagRule::SevenBag, preview: DEFAULT_PREVIEW, hold: true, placements: true }
+    }
+}
+
+// Player handling in milliseconds, as `TetrisEngine::configure_movement`
+// takes it; an sdf of u32::MAX drops instantly. Each input moves the piece
+// as far as it goes at once, so the simulator only records these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handling {
+    pub arr: u32,
+    pub das: u32,
+    pub sdf: u32,
+    pub dcd: u32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Self { arr: 16, das: 133, sdf: u32::MAX, dcd: 0 }
+    }
+}
+
+// Headless game with a seeded randomizer: the active piece is locked
+// wherever a placement puts it, as long as the move generator can reach that
+// spot, or moved input by input and hard dropped.
+pub struct Game {
+    board: Board,
+    rng: PieceRng,
+    rules: Ruleset,
+    // What the bag still owes after the last piece dealt
+    bag: BagState,
+    // Active piece first, then the preview
+    queue: VecDeque<PieceType>,
+    hold: Option<PieceType>,
+    // Hold was used since the last lock
+    held: bool,
+    // Where inputs have moved the active piece
+    active: Option<Piece>,
+    // The last input that moved the active piece was a rotation, so a hard
+    // drop can score a spin
+    rotated: bool,
+    // Locks in a row that cleared lines
+    combo: u32,
+    // The last clear was a tetris or a spin
+    b2b: bool,
+    stats: GameStats,
+    // Every lock so far, for export; `replay::Replay` records whole games
+    placements: Vec<PlacementRecord>,
+    over: bool,
+}
+
+impl Game {
+    pub fn new(seed: u64, preview: usize) -> Self {
+        Self::with_rules(seed, Ruleset { preview, ..Ruleset::default() })
+    }
+
+    pub fn with_rules(seed: u64, rules: Ruleset) -> Self {
+        let mut game = Self {
+            board: Board::new().with_colors(),
+            rng: PieceRng::new(seed),
+            rules,
+            bag: BagState::full(),
+            queue: VecDeque::new(),
+            hold: None,
+            held: false,
+            active: None,
+            rotated: false,
+            combo: 0,
+            b2b: false,
+            stats: GameStats::default(),
+            placements: Vec::new(),
+            over: false,
+        };
+        while game.queue.len() <= rules.preview {
+            game.deal();
+        }
+        game.spawn();
+        game
+    }
+
+    fn deal(&mut self) {
+        let piece = self.rules.bag.deal(&self.bag, &mut self.rng);
+        self.bag = self.bag.take(piece);
+        self.queue.push_back(piece);
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // Bring in the next active piece; the game is over if it does not fit
+    fn spawn(&mut self) {
+        self.active = self.queue.front().copied().map(Piece::spawn).filter(|piece| self.board.can_place_piece(piece));
+        self.rotated = false;
+        self.over = self.active.is_none();
+    }
+
+    pub fn rules(&self) -> &Ruleset {
+        &self.rules
+    }
+
+    pub fn current(&self) -> Option<PieceType> {
+        if self.over { None } else { self.queue.front().copied() }
+    }
+
+    pub fn active(&self) -> Option<Piece> {
+        self.active
+    }
+
+    pub fn hold_piece(&self) -> Option<PieceType> {
+        self.hold
+    }
+
+    // Whether hold can be used before the active piece locks
+    pub fn can_hold(&self) -> bool {
+        self.rules.hold && !self.held && !self.over
+    }
+
+    pub fn preview(&self) -> Vec<PieceType> {
+        self.queue.iter().skip(1).copied().collect()
+    }
+
+    pub fn bag(&self) -> BagState {
+        self.bag
+    }
+
+    pub fn stats(&self) -> &GameStats {
+        &self.stats
+    }
+
+    pub fn placements(&self) -> &[PlacementRecord] {
+        &self.placements
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    // Lock the active piece at `placement`; false, leaving the game as it was,
+    // if the game is over or the piece cannot get there. A spin spot scores as
+    // a spin, as if the piece had rotated in last.
+    pub fn play(&mut self, placement: &Placement) -> bool {
+        self.lock(placement, true)
+    }
+
+    // Like `play`, crediting a spin only if `rotated`
+    fn lock(&mut self, placement: &Placement, rotated: bool) -> bool {
+        let Some(piece_type) = self.current() else {
+            return false;
+        };
+        let moves = MoveMap::new(&self.board, piece_type);
+        if !moves.placements().contains(placement) {
+            return false;
+        }
+
+        self.board.lock_piece(&Piece::new(piece_type, placement.x, placement.y).with_rotation(placement.rotation));
+        let lines = self.board.clear_lines().lines_cleared;
+        self.stats.pieces += 1;
+        self.stats.lines += lines;
+        self.stats.clears[lines.min(4)] += 1;
+        let spin = if rotated { moves.spin(placement) } else { SpinKind::None };
+        match spin {
+            SpinKind::Full => self.stats.spins += 1,
+            SpinKind::Mini => self.stats.mini_spins += 1,
+            SpinKind::None => {}
+        }
+        if lines > 0 && self.board.rows.iter().all(|&row| row == 0) {
+            self.stats.perfect_clears += 1;
+        }
+        self.score(lines, spin);
+        self.placements.push(PlacementRecord { piece: piece_type, placement: *placement, hold: self.held });
+
+        self.queue.pop_front();
+        self.deal();
+        self.held = false;
+        self.spawn();
+        true
+    }
+
+    fn score(&mut self, lines: usize, spin: SpinKind) {
+        let mut points = match spin {
+            SpinKind::Full => [400, 800, 1200, 1600][lines.min(3)],
+            SpinKind::Mini => [100, 200, 400][lines.min(2)],
+            SpinKind::None => [0, 100, 300, 500, 800][lines.min(4)],
+        };
+        if lines == 0 {
+            self.combo = 0;
+        } else {
+            let difficult = lines >= 4 || spin != SpinKind::None;
+            if difficult && self.b2b {
+                points += points / 2;
+            }
+            self.b2b = difficult;
+            points += 50 * self.combo as u64;
+            self.combo += 1;
+            if self.board.rows.iter().all(|&row| row == 0) {
+                points += [0, 800, 1200, 1800, 2000][lines.min(4)];
+            }
+        }
+        self.stats.score += points;
+    }
+
+    // Locks in a row that cleared lines, and whether the last clear was a
+    // tetris or a spin
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    pub fn back_to_back(&self) -> bool {
+        self.b2b
+    }
+
+    // Move the active piece; false, leaving it where it was, if it is blocked
+    pub fn input(&mut self, input: Input) -> bool {
+        let Some(moved) = self.active.and_then(|piece| input.apply(&self.board, &piece)) else {
+            return false;
+        };
+        self.active = Some(moved);
+        self.rotated = matches!(input, Input::RotateCw | Input::RotateCcw);
+        true
+    }
+
+    // Swap the active piece with the hold slot, or with the next piece when
+    // the slot is empty; false if the rules or the one hold per piece forbid it
+    pub fn hold(&mut self) -> bool {
+        if !self.can_hold() {
+            return false;
+        }
+        let Some(current) = self.queue.pop_front() else {
+            return false;
+        };
+        match self.hold.replace(current) {
+            Some(held) => self.queue.push_front(held),
+            None => self.deal(),
+        }
+        self.held = true;
+        self.spawn();
+        true
+    }
+
+    // Where a hard drop would lock the active piece
+    pub fn landing(&self) -> Option<Placement> {
+        let active = self.active?;
+        let piece = Input::SonicDrop.apply(&self.board, &active).unwrap_or(active);
+        Some(Placement::new(piece.x, piece.y, piece.rotation))
+    }
+
+    // Lock the active piece where it lands; it only scores a spin if the
+    // last input that moved it was a rotation
+    pub fn hard_drop(&mut self) -> Option<Placement> {
+        let placement = self.landing()?;
+        self.lock(&placement, self.rotated).then_some(placement)
+    }
+
+    // Play a move sequence from spawn, as the bot or a player sends it; like
+    // `hard_drop`, it only scores a spin if the last input that moved the
+    // piece was a rotation
+    pub fn play_inputs(&mut self, actions: &[String]) -> Option<Placement> {
+        let (piece, rotated) = movegen::trace_inputs(&self.board, self.current()?, actions)?;
+        let placement = Placement::new(piece.x, piece.y, piece.rotation);
+        self.lock(&placement, rotated).then_some(placement)
+    }
+
+    // Where the bot would lock the active piece
+    pub fn bot_placement(&self, search: &mut SearchEngine, strategy: Strategy) -> Option<Placement> {
+        let piece_type = self.current()?;
+        search.set_bag(self.rules.bag, self.bag);
+        let result = search.search_with_tree(&self.board, piece_type, &self.preview(), strategy, 0, 0, false);
+        let actions: Vec<String> = result.best_move.split(',').map(str::to_string).collect();
+        let piece = movegen::play_inputs(&self.board, piece_type, &actions)?;
+        Some(Placement::new(piece.x, piece.y, piece.rotation))
+    }
+
+    // Let the bot play up to `max_pieces`; returns how many it placed
+    pub fn play_bot(&mut self, search: &mut SearchEngine, strategy: Strategy, max_pieces: usize) -> usize {
+        let mut placed = 0;
+        while placed < max_pieces {
+            match self.bot_placement(search, strategy) {
+                Some(placement) if self.play(&placement) => placed += 1,
+                _ => break,
+            }
+        }
+        placed
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_TABLE_ENTRIES: usize = 1 << 16;
+
+// Fixed-size, always-replace table of search values. Each slot stores the
+// value and `key ^ value`, so a torn write from another thread reads as a
+// miss instead of a wrong value; no locks are needed under rayon.
+pub struct TranspositionTable {
+    slots: Vec<[AtomicU64; 2]>,
+    mask: usize,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_ENTRIES)
+    }
+}
+
+impl TranspositionTable {
+    // Rounded up to a power of two
+    pub fn new(entries: usize) -> Self {
+        let entries = entries.max(1).next_power_of_two();
+        Self {
+            slots: (0..entries).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect(),
+            mask: entries - 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn probe(&self, key: u64) -> Option<f64> {
+        let [check, value] = &self.slots[key as usize & self.mask];
+        let value = value.load(Ordering::Relaxed);
+        let check = check.load(Ordering::Relaxed);
+        // An untouched slot is all zeroes, which would match key 0
+        (check ^ value == key && (check | value) != 0).then(|| f64::from_bits(value))
+    }
+
+    pub fn store(&self, key: u64, value: f64) {
+        let [check, slot] = &self.slots[key as usize & self.mask];
+        let bits = value.to_bits();
+        slot.store(bits, Ordering::Relaxed);
+        check.store(key ^ bits, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for [check, value] in &self.slots {
+            check.store(0, Ordering::Relaxed);
+            value.store(0, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::replay::{Replay, ReplayFault, ReplayPlayer, FRAME_RATE};
+
+// Longest preview a submission may declare. Pieces are dealt before the
+// first event, so this bounds the work a replay can ask for up front.
+pub const MAX_PREVIEW: usize = 7;
+
+// What a submission says its replay achieved
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClaimedResult {
+    pub score: u64,
+    pub lines: usize,
+    // Up to the last event; anything within a frame of the replay counts
+    pub time_ms: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum RejectReason {
+    // The declared rules are outside what the verifier plays
+    Rules,
+    // An event the rules forbid or the game cannot follow
+    Illegal { event: usize, fault: ReplayFault },
+    Lines { claimed: usize, actual: usize },
+    Score { claimed: u64, actual: u64 },
+    Time { claimed_ms: u64, actual_ms: u64 },
+}
+
+// The first frame that does not match the claim: where an illegal event
+// sits, or the end of the game when only the result differs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Rejection {
+    pub frame: u32,
+    pub reason: RejectReason,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame {}: ", self.frame)?;
+        match self.reason {
+            RejectReason::Rules => write!(f, "rules outside what can be verified"),
+            RejectReason::Illegal { event, fault } => write!(f, "event {}: {}", event + 1, fault),
+            RejectReason::Lines { claimed, actual } => write!(f, "claimed {} lines, replay clears {}", claimed, actual),
+            RejectReason::Score { claimed, actual } => write!(f, "claimed {} points, replay scores {}", claimed, actual),
+            RejectReason::Time { claimed_ms, actual_ms } => write!(f, "claimed {} ms, replay lasts {} ms", claimed_ms, actual_ms),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+// Re-simulates `replay` under its declared rules and checks `claimed`
+// against the result; Ok holds what the replay achieved. Placement events are
+// accepted when the rules allow them, and those only prove the spot was
+// reachable: callers ranking humans should require `rules.placements` off.
+pub fn verify(replay: &Replay, claimed: &ClaimedResult) -> Result<ClaimedResult, Rejection> {
+    if replay.rules.preview > MAX_PREVIEW {
+        return Err(Rejection { frame: 0, reason: RejectReason::Rules });
+    }
+    let game = ReplayPlayer::new(replay).run().map_err(|divergence| Rejection {
+        frame: divergence.frame,
+        reason: RejectReason::Illegal { event: divergence.event, fault: divergence.fault },
+    })?;
+
+    let frame = replay.frames();
+    let actual = ClaimedResult {
+        score: game.stats().score,
+        lines: game.stats().lines,
+        time_ms: frame as u64 * 1000 / FRAME_RATE as u64,
+    };
+    let reason = if claimed.lines != actual.lines {
+        RejectReason::Lines { claimed: claimed.lines, actual: actual.lines }
+    } else if claimed.score != actual.score {
+        RejectReason::Score { claimed: claimed.score, actual: actual.score }
+    } else if claimed.time_ms.abs_diff(actual.time_ms) > 1000 / FRAME_RATE as u64 {
+        RejectReason::Time { claimed_ms: claimed.time_ms, actual_ms: actual.time_ms }
+    } else {
+        return Ok(actual);
+    };
+    Err(Rejection { frame, reason })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::Strategy;
+    use crate::evaluation::SpinKind;
+    use crate::movegen::{Input, MoveMap};
+    use crate::pieces::{PieceType, Placement};
+    use crate::replay::{Recorder, ReplayEvent, ReplayInput, ReplayPlayer};
+    use crate::search::SearchEngine;
+    use crate::sim::{Game, Handling, Ruleset};
+
+    fn claim(recorder: &Recorder) -> ClaimedResult {
+        let stats = recorder.game().stats();
+        let time_ms = recorder.replay().frames() as u64 * 1000 / FRAME_RATE as u64;
+        ClaimedResult { score: stats.score, lines: stats.lines, time_ms }
+    }
+
+    fn input(frame: u32, input: ReplayInput) -> ReplayEvent {
+        ReplayEvent::Input { frame, input }
+    }
+
+    #[test]
+    fn accepts_recorded_bot_game() {
+        let mut recorder = Recorder::new(7, Ruleset::default(), Handling::default());
+        assert_eq!(recorder.play_bot(&mut SearchEngine::new(), Strategy::Balanced, 30), 30);
+        let claimed = claim(&recorder);
+        assert!(claimed.lines > 0);
+        assert_eq!(verify(recorder.replay(), &claimed), Ok(claimed));
+
+        let padded = ClaimedResult { score: claimed.score + 100, ..claimed };
+        assert!(matches!(verify(recorder.replay(), &padded), Err(Rejection { reason: RejectReason::Score { .. }, .. })));
+    }
+
+    #[test]
+    fn accepts_recorded_inputs() {
+        let rules = Ruleset { placements: false, ..Ruleset::default() };
+        let mut recorder = Recorder::new(3, rules, Handling::default());
+        for (index, step) in [ReplayInput::Move(Input::DasLeft), ReplayInput::HardDrop, ReplayInput::Hold, ReplayInput::HardDrop].into_iter().enumerate() {
+            recorder.advance_to(index as u32 * 10);
+            assert!(recorder.input(step));
+        }
+        let claimed = claim(&recorder);
+        assert_eq!(verify(recorder.replay(), &claimed), Ok(claimed));
+    }
+
+    #[test]
+    fn rejects_placements_when_disabled() {
+        let rules = Ruleset { placements: false, ..Ruleset::default() };
+        let game = Game::with_rules(3, rules);
+        let mut replay = Replay::new(3, rules, Handling::default());
+        let piece = game.current().unwrap();
+        replay.events.push(ReplayEvent::Placement { frame: 0, piece, placement: game.landing().unwrap() });
+        let rejection = verify(&replay, &ClaimedResult { score: 0, lines: 0, time_ms: 0 }).unwrap_err();
+        assert_eq!(rejection.reason, RejectReason::Illegal { event: 0, fault: ReplayFault::PlacementsDisabled });
+    }
+
+    #[test]
+    fn rejects_second_hold() {
+        let mut replay = Replay::new(1, Ruleset::default(), Handling::default());
+        replay.events.extend([input(0, ReplayInput::Hold), input(5, ReplayInput::Hold)]);
+        let rejection = verify(&replay, &ClaimedResult { score: 0, lines: 0, time_ms: 83 }).unwrap_err();
+        assert_eq!(rejection, Rejection { frame: 5, reason: RejectReason::Illegal { event: 1, fault: ReplayFault::SecondHold } });
+    }
+
+    #[test]
+    fn rejects_blocked_rotation() {
+        // Stood up against the left wall, the piece has no room to lie flat
+        // again; rotation has no kicks. O never leaves its box, so skip it.
+        let seed = (1..).find(|&seed| Game::new(seed, 1).current() != Some(PieceType::O)).unwrap();
+        let mut replay = Replay::new(seed, Ruleset::default(), Handling::default());
+        replay.events.extend([
+            input(0, ReplayInput::Move(Input::RotateCw)),
+            input(1, ReplayInput::Move(Input::DasLeft)),
+            input(2, ReplayInput::Move(Input::RotateCcw)),
+        ]);
+        let rejection = verify(&replay, &ClaimedResult { score: 0, lines: 0, time_ms: 33 }).unwrap_err();
+        let fault = ReplayFault::Blocked(Input::RotateCcw);
+        assert_eq!(rejection, Rejection { frame: 2, reason: RejectReason::Illegal { event: 2, fault } });
+    }
+
+    #[test]
+    fn shifting_into_a_spin_spot_scores_no_spin() {
+        // Seed 154 deals S J T Z I O L T; the first seven leave a spot the
+        // second T can rotate into, or reach by sliding right along the stack
+        let mut recorder = Recorder::new(154, Ruleset::default(), Handling::default());
+        for (x, y, rotation) in [(1, 38, 0), (3, 37, 3), (7, 38, 0), (0, 36, 1), (-2, 36, 1), (4, 37, 0), (8, 37, 3)] {
+            assert!(recorder.place(Placement::new(x, y, rotation)));
+            recorder.advance(1);
+        }
+        let slot = Placement::new(6, 37, 3);
+        assert_eq!(recorder.game().current(), Some(PieceType::T));
+        assert_eq!(MoveMap::new(recorder.game().board(), PieceType::T).spin(&slot), SpinKind::Mini);
+
+        let slide = [Input::RotateCcw, Input::SonicDrop, Input::DasRight];
+        let mut game = ReplayPlayer::new(recorder.replay()).run().unwrap();
+        let actions: Vec<String> = slide.iter().map(|input| input.action().to_string()).chain(["hard_drop".to_string()]).collect();
+        assert_eq!(game.play_inputs(&actions), Some(slot));
+        assert_eq!((game.stats().spins, game.stats().mini_spins), (0, 0));
+
+        // The same inputs as events verify at the score `play_inputs` gave
+        for input in slide {
+            assert!(recorder.input(ReplayInput::Move(input)));
+        }
+        assert!(recorder.input(ReplayInput::HardDrop));
+        let claimed = claim(&recorder);
+        assert_eq!(claimed.score, game.stats().score);
+        assert_eq!(verify(recorder.replay(), &claimed), Ok(claimed));
+    }
+}
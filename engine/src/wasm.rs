@@ -0,0 +1,211 @@
+extern crate console_error_panic_hook;
+use wasm_bindgen::prelude::*;
+
+use crate::engine::TetrisEngine;
+use crate::evaluation::Strategy;
+use crate::fumen;
+use crate::bag::BagRule;
+use crate::anytime::SearchBudget;
+use crate::logging::{self, LogSink};
+use crate::pieces::Placement;
+use crate::console_log;
+
+// === WASM INTERFACE ===
+
+#[wasm_bindgen]
+pub struct WasmTetrisEngine {
+    engine: TetrisEngine,
+}
+
+impl Default for WasmTetrisEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmTetrisEngine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        console_log!("Advanced Tetris AI Engine initialized");
+        Self {
+            engine: TetrisEngine::new(),
+        }
+    }
+
+    #[wasm_bindgen(js_name = configureMovement)]
+    pub fn configure_movement(&mut self, arr: u32, das: u32, sdf: u32, dcd: u32) {
+        self.engine.configure_movement(arr, das, sdf, dcd);
+    }
+
+    #[wasm_bindgen(js_name = configureLogging)]
+    pub fn configure_logging(&mut self, debug: bool) {
+        self.engine.configure_logging(debug);
+    }
+
+    // "console" (default), "silent" or "stderr"; false for an unknown name
+    #[wasm_bindgen(js_name = configureLogSink)]
+    pub fn configure_log_sink(&mut self, sink: &str) -> bool {
+        LogSink::from_name(sink).map(logging::set_log_sink).is_some()
+    }
+
+    #[wasm_bindgen(js_name = configureLookahead)]
+    pub fn configure_lookahead(&mut self, beam_width: u32, chance_depth: u32) {
+        self.engine.configure_lookahead(beam_width as usize, chance_depth as usize);
+    }
+
+    // Switch between beam search and MCTS with the given iteration count
+    #[wasm_bindgen(js_name = configureMcts)]
+    pub fn configure_mcts(&mut self, enabled: bool, iterations: u32) {
+        self.engine.configure_mcts(enabled, iterations as usize);
+    }
+
+    // Milliseconds of search on the next piece per get_best_move call while
+    // the current one falls; 0 turns pondering off
+    #[wasm_bindgen(js_name = configurePondering)]
+    pub fn configure_pondering(&mut self, budget_ms: f64) {
+        self.engine.configure_pondering(budget_ms);
+    }
+
+    #[wasm_bindgen(js_name = configurePerfectClear)]
+    pub fn configure_perfect_clear(&mut self, enabled: bool, max_height: u32) {
+        self.engine.configure_perfect_clear(enabled, max_height as usize);
+    }
+
+    // Returns the PC solution as JSON, or "null" when none exists
+    pub fn find_perfect_clear(&self, board: Vec<i32>, current_piece: i32, hold_piece: i32, queue: Vec<i32>, max_height: u32) -> String {
+        let solution = self.engine.find_perfect_clear(&board, current_piece, hold_piece, &queue, max_height as usize);
+        serde_json::to_string(&solution).unwrap_or_else(|_| "null".to_string())
+    }
+
+    // `queue` starts with the current piece; `bag_remaining` lists the pieces the
+    // current bag still owes after it (empty when the next piece opens a new bag)
+    pub fn pc_probability(&self, board: Vec<i32>, hold_piece: i32, queue: Vec<i32>, bag_remaining: Vec<i32>, rule: BagRule, max_height: u32) -> String {
+        let result = self.engine.pc_probability(&board, hold_piece, &queue, &bag_remaining, rule, max_height as usize);
+        serde_json::to_string(&result).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    pub fn get_best_move(&mut self, board: Vec<i32>, current_piece: i32, next_piece: i32, strategy: Strategy) -> String {
+        self.engine.get_best_move(&board, current_piece, next_piece, strategy)
+    }
+
+    pub fn get_full_move_sequence(&mut self, board: Vec<i32>, current_piece: i32, next_piece: i32, strategy: Strategy) -> String {
+        self.engine.get_full_move_sequence(&board, current_piece, next_piece, strategy)
+    }
+
+    // JSON array of the `count` best placements, best first, each with its
+    // cells, score, move path and the board after clearing
+    pub fn top_placements(&mut self, board: Vec<i32>, current_piece: i32, next_piece: i32, strategy: Strategy, count: u32) -> String {
+        let placements = self.engine.top_placements(&board, current_piece, next_piece, strategy, count as usize);
+        serde_json::to_string(&placements).unwrap_or_else(|_| "[]".to_string())
+    }
+
+    // The same candidates as a fumen to open in an editor, a page each
+    pub fn top_placements_fumen(&mut self, board: Vec<i32>, current_piece: i32, next_piece: i32, strategy: Strategy, count: u32) -> String {
+        self.engine.top_placements_fumen(&board, current_piece, next_piece, strategy, count as usize)
+    }
+
+    // Visible rows of one page of a fumen, in the layout the other methods
+    // take; empty if the fumen cannot be read or has no such page
+    pub fn fumen_board(&self, fumen: String, page: u32) -> Vec<i32> {
+        fumen::decode(&fumen)
+            .ok()
+            .and_then(|pages| pages.into_iter().nth(page as usize))
+            .map(|page| page.board.to_flat_array())
+            .unwrap_or_default()
+    }
+
+    // Per-feature score breakdown of placing the piece at (x, y, rotation), in
+    // engine coordinates (row 20 is the top visible row), as JSON; "null" if
+    // the piece cannot come to rest there
+    pub fn explain_placement(&self, board: Vec<i32>, current_piece: i32, x: i32, y: i32, rotation: u32, strategy: Strategy) -> String {
+        let placement = Placement::new(x, y, rotation as usize);
+        let explanation = self.engine.explain_placement(&board, current_piece, placement, strategy);
+        serde_json::to_string(&explanation).unwrap_or_else(|_| "null".to_string())
+    }
+
+    // Coaching: rank, value gap and severity of the placement a human made,
+    // plus what the engine would have played, as JSON; "null" if the piece
+    // cannot come to rest at (x, y, rotation)
+    #[allow(clippy::too_many_arguments)]
+    pub fn grade_placement(&mut self, board: Vec<i32>, current_piece: i32, next_piece: i32, x: i32, y: i32, rotation: u32, strategy: Strategy) -> String {
+        let placement = Placement::new(x, y, rotation as usize);
+        let grade = self.engine.grade_placement(&board, current_piece, next_piece, placement, strategy);
+        serde_json::to_string(&grade).unwrap_or_else(|_| "null".to_string())
+    }
+
+    // Finesse check of one piece's inputs, comma-separated in the same action
+    // names as move sequences and ending with "hard_drop", as JSON; "null" if
+    // the sequence cannot be played
+    pub fn check_finesse(&self, board: Vec<i32>, current_piece: i32, inputs: String) -> String {
+        let inputs: Vec<String> = inputs.split(',').map(|action| action.trim().to_string()).collect();
+        let report = self.engine.check_finesse(&board, current_piece, &inputs);
+        serde_json::to_string(&report).unwrap_or_else(|_| "null".to_string())
+    }
+
+    // Anytime search: start once per piece, then call `think` with a few
+    // milliseconds each frame and `poll` for the best move sequence so far.
+    pub fn start_thinking(&mut self, board: Vec<i32>, current_piece: i32, next_piece: i32, strategy: Strategy) {
+        self.engine.start_thinking(&board, current_piece, next_piece, strategy);
+    }
+
+    pub fn think(&mut self, budget_ms: f64) -> bool {
+        self.engine.think(SearchBudget::time(budget_ms))
+    }
+
+    pub fn think_nodes(&mut self, nodes: u32) -> bool {
+        self.engine.think(SearchBudget::nodes(nodes as usize))
+    }
+
+    // Empty when no search has been started
+    pub fn poll(&self) -> String {
+        self.engine.poll().unwrap_or_default()
+    }
+
+    // Legacy methods for compatibility
+    pub fn move_left(&mut self) -> bool {
+        console_log!("WasmTetrisEngine::move_left() called");
+        false
+    }
+
+    pub fn move_right(&mut self) -> bool {
+        console_log!("WasmTetrisEngine::move_right() called");
+        false
+    }
+
+    pub fn move_down(&mut self) -> bool {
+        console_log!("WasmTetrisEngine::move_down() called");
+        false
+    }
+
+    pub fn rotate(&mut self) -> bool {
+        console_log!("WasmTetrisEngine::rotate() called");
+        false
+    }
+
+    pub fn spawn_tetromino(&mut self, type_key: i32) -> bool {
+        console_log!("WasmTetrisEngine::spawn_tetromino({}) called", type_key);
+        false
+    }
+
+    // Everything `load_game_state_json` needs to pick the game up again
+    pub fn get_game_state_json(&self) -> String {
+        serde_json::to_string(&self.engine.game_state()).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    pub fn load_game_state_json(&mut self, json: String) -> bool {
+        let loaded = serde_json::from_str(&json).map_err(|error| error.to_string()).and_then(|state| self.engine.load_game_state(state));
+        match loaded {
+            Ok(()) => true,
+            Err(error) => {
+                console_log!("Could not load game state: {}", error);
+                false
+            }
+        }
+    }
+}
+
+#[wasm_bindgen(start)]
+pub fn start() {
+    console_log!("🚀🚀🚀 WASM LOADED: {} - SMART FLAT BUILDER (GEOMETRY-AWARE) 🚀🚀🚀", "2024-06-06 18:05");
+} 
\ No newline at end of file
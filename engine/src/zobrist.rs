@@ -0,0 +1,94 @@
+use crate::board::{Board, BOARD_HEIGHT, BOARD_WIDTH};
+use crate::pieces::PieceType;
+
+// Preview slots with their own keys; deeper slots reuse them cyclically
+const QUEUE_SLOTS: usize = 8;
+// Combos past this share a key
+const MAX_COMBO_KEY: usize = 15;
+
+// SplitMix64 finalizer, usable in const context to bake the key tables
+pub const fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub const CELL_KEYS: [[u64; BOARD_WIDTH]; BOARD_HEIGHT] = {
+    let mut keys = [[0u64; BOARD_WIDTH]; BOARD_HEIGHT];
+    let mut y = 0;
+    while y < BOARD_HEIGHT {
+        let mut x = 0;
+        while x < BOARD_WIDTH {
+            keys[y][x] = mix((y * BOARD_WIDTH + x) as u64);
+            x += 1;
+        }
+        y += 1;
+    }
+    keys
+};
+
+// Index 7 is the empty hold slot
+const HOLD_KEYS: [u64; 8] = {
+    let mut keys = [0u64; 8];
+    let mut i = 0;
+    while i < 8 {
+        keys[i] = mix(0x1000 + i as u64);
+        i += 1;
+    }
+    keys
+};
+
+const QUEUE_KEYS: [[u64; 7]; QUEUE_SLOTS] = {
+    let mut keys = [[0u64; 7]; QUEUE_SLOTS];
+    let mut slot = 0;
+    while slot < QUEUE_SLOTS {
+        let mut piece = 0;
+        while piece < 7 {
+            keys[slot][piece] = mix(0x2000 + (slot * 7 + piece) as u64);
+            piece += 1;
+        }
+        slot += 1;
+    }
+    keys
+};
+
+const COMBO_KEYS: [u64; MAX_COMBO_KEY + 1] = {
+    let mut keys = [0u64; MAX_COMBO_KEY + 1];
+    let mut i = 0;
+    while i <= MAX_COMBO_KEY {
+        keys[i] = mix(0x3000 + i as u64);
+        i += 1;
+    }
+    keys
+};
+
+const B2B_KEY: u64 = mix(0x4000);
+
+// Hash of every filled cell; `Board` keeps this up to date incrementally
+pub fn board_hash(rows: &[u32; BOARD_HEIGHT]) -> u64 {
+    let mut hash = 0;
+    for (y, &row) in rows.iter().enumerate() {
+        let mut bits = row;
+        while bits != 0 {
+            let x = bits.trailing_zeros() as usize;
+            hash ^= CELL_KEYS[y][x];
+            bits &= bits - 1;
+        }
+    }
+    hash
+}
+
+// Full search position: board, hold slot, the known pieces still to come,
+// combo counter and back-to-back flag
+pub fn state_hash(board: &Board, hold: Option<PieceType>, queue: &[PieceType], combo: u32, b2b: bool) -> u64 {
+    let mut hash = board.hash() ^ HOLD_KEYS[hold.map_or(7, |piece| piece as usize)];
+    for (slot, &piece) in queue.iter().enumerate() {
+        hash ^= QUEUE_KEYS[slot % QUEUE_SLOTS][piece as usize].rotate_left((slot / QUEUE_SLOTS) as u32);
+    }
+    hash ^= COMBO_KEYS[(combo as usize).min(MAX_COMBO_KEY)];
+    if b2b {
+        hash ^= B2B_KEY;
+    }
+    hash
+}